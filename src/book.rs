@@ -0,0 +1,183 @@
+//! This mod defines the Book, the project of many documents.
+//! All documents share one ObjectStore, each document has its own refs:
+//! ```text
+//! <root>/objects/ab/cdef...
+//! <root>/refs/<file>.refs/main
+//! <root>/refs/<file>.refs/contributors/<contributor>/<branch>
+//! <root>/refs/<file>.refs/releases/<name>
+//! ```
+//! The `.refs` suffix keeps the refs of a file apart from the files nested under it,
+//! e.g. the refs of `ch01` and `ch01/releases`.
+
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use crate::document::Document;
+use crate::history::{
+    History, HistoryError, Result, RevisionCache, SnapshotPolicy, CACHE_CAPACITY,
+};
+use crate::macros::s;
+use crate::refs::RefStore;
+use crate::store::ObjectStore;
+
+/// example:
+/// ```ignore
+/// let book = Book::new("/var/lib/carbon/rust-book", s!("eq99"));
+/// let commit = book.history("ch01/installation.md")?.commit(MAIN, &doc, "eq99", "create")?;
+/// let doc = book.checkout("ch01/installation.md", &commit)?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct Book {
+    root: PathBuf,
+    maintainer: String,
+    store: ObjectStore,
+    cache: Arc<Mutex<RevisionCache>>,
+    policy: SnapshotPolicy,
+}
+
+impl Book {
+    /// Create a Book rooted at `root`, the `maintainer` owns the mainline of every document.
+    pub fn new<P: Into<PathBuf>>(root: P, maintainer: String) -> Self {
+        let root = root.into();
+        let store = ObjectStore::new(root.join("objects"));
+        let cache = Arc::new(Mutex::new(RevisionCache::new(CACHE_CAPACITY)));
+        Self {
+            root,
+            maintainer,
+            store,
+            cache,
+            policy: SnapshotPolicy::default(),
+        }
+    }
+
+    /// Set the SnapshotPolicy of every document.
+    pub fn with_snapshot_policy(self, policy: SnapshotPolicy) -> Self {
+        Self { policy, ..self }
+    }
+
+    /// Set the capacity of the cache of recently checked out Documents.
+    pub fn with_cache_capacity(self, capacity: usize) -> Self {
+        let cache = Arc::new(Mutex::new(RevisionCache::new(capacity)));
+        Self { cache, ..self }
+    }
+
+    pub fn store(&self) -> &ObjectStore {
+        &self.store
+    }
+
+    /// The history of a file, all histories share the cache of the Book.
+    /// The file name is a relative path like `ch01/installation.md`,
+    /// it should not contain `.` or `..` segments, or segments ending with `.refs`.
+    pub fn history(&self, file: &str) -> Result<History> {
+        let valid = file.split('/').all(|segment| {
+            !segment.is_empty()
+                && segment != "."
+                && segment != ".."
+                && !segment.contains('\\')
+                && !segment.ends_with(".refs")
+        });
+        if !valid {
+            return Err(HistoryError::InvalidFile(s!(file)));
+        }
+        let refs_root = file
+            .split('/')
+            .fold(self.root.join("refs"), |path, s| path.join(s));
+        let refs_root = refs_root.with_file_name(format!(
+            "{}.refs",
+            refs_root.file_name().unwrap().to_string_lossy()
+        ));
+        let refs = RefStore::new(refs_root, self.maintainer.clone());
+        Ok(History::new(self.store.clone(), refs)
+            .with_cache(self.cache.clone())
+            .with_snapshot_policy(self.policy))
+    }
+
+    /// Reconstruct the Document of a file at a Commit.
+    pub fn checkout(&self, file: &str, commit: &str) -> Result<Document> {
+        self.history(file)?.checkout(commit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use self::super::*;
+    use crate::refs::MAIN;
+
+    #[test]
+    // #[ignore]
+    fn test_checkout() {
+        let dir = tempfile::tempdir().unwrap();
+        let book = Book::new(dir.path(), s!("eq99"));
+        let base = Document::from_fs(s!("tests/base"));
+        let one = Document::from_fs(s!("tests/change_one"));
+        let all = Document::from_fs(s!("tests/change_all"));
+
+        let ch1 = book.history("ch01/intro.md").unwrap();
+        let c1 = ch1.commit(MAIN, &base, "eq99", "create").unwrap();
+        let c2 = ch1.commit(MAIN, &one, "eq99", "edit").unwrap();
+        let ch2 = book.history("ch02/intro.md").unwrap();
+        let c3 = ch2.commit(MAIN, &all, "eq99", "create").unwrap();
+
+        assert!(dir.path().join("refs/ch01/intro.md.refs/main").is_file());
+        assert!(dir.path().join("refs/ch02/intro.md.refs/main").is_file());
+        assert_eq!(base, book.checkout("ch01/intro.md", &c1).unwrap());
+        assert_eq!(one, book.checkout("ch01/intro.md", &c2).unwrap());
+        assert_eq!(all, book.checkout("ch02/intro.md", &c3).unwrap());
+
+        // the cache is shared
+        assert_eq!(3, book.cache.lock().unwrap().len());
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_invalid_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let book = Book::new(dir.path(), s!("eq99"));
+        for file in &[
+            "",
+            "/etc/passwd",
+            "../a.md",
+            "ch01/./a.md",
+            "ch01//a.md",
+            "ch01.refs/a.md",
+        ] {
+            assert!(matches!(
+                book.history(file),
+                Err(HistoryError::InvalidFile(_))
+            ));
+        }
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_nested_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let book = Book::new(dir.path(), s!("eq99"));
+        let a = Document::from_content("A\n");
+        let b = Document::from_content("B\n");
+        let c = Document::from_content("C\n");
+
+        let c1 = book
+            .history("ch01")
+            .unwrap()
+            .commit(MAIN, &a, "eq99", "create")
+            .unwrap();
+        let c2 = book
+            .history("ch01/releases")
+            .unwrap()
+            .commit(MAIN, &b, "eq99", "create")
+            .unwrap();
+        let c3 = book
+            .history("ch01/main")
+            .unwrap()
+            .commit(MAIN, &c, "eq99", "create")
+            .unwrap();
+
+        let ch01 = book.history("ch01").unwrap();
+        assert_eq!(vec![(s!(MAIN), c1.clone())], ch01.refs().list().unwrap());
+        assert!(ch01.releases().unwrap().is_empty());
+        assert_eq!(a, book.checkout("ch01", &c1).unwrap());
+        assert_eq!(b, book.checkout("ch01/releases", &c2).unwrap());
+        assert_eq!(c, book.checkout("ch01/main", &c3).unwrap());
+    }
+}
@@ -0,0 +1,251 @@
+//! This mod defines the Commit object.
+//! Commits are the nodes of the history of a document,
+//! each Commit records the hash of a Patch and the hash of its parent Commit,
+//! so `v0 + p1 + p2 + p3 = v3` can be replayed by walking the parents.
+
+use std::io::{Error, ErrorKind, Result};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::macros::s;
+use crate::store::ObjectStore;
+
+/// The version of commit encoding, see `Commit::encode`.
+pub const COMMIT_VERSION: usize = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Commit {
+    /// hash of the Patch from the parent's document to this commit's document
+    pub patch: String,
+    /// hash of the parent Commit, None for the first commit of a document
+    pub parent: Option<String>,
+    /// author name, it should be a single line
+    pub author: String,
+    /// seconds since unix epoch
+    pub timestamp: u64,
+    pub message: String,
+    /// hash of the full Document at this Commit,
+    /// it's written by the snapshot policy to bound the length of Patch chain, see History.
+    pub snapshot: Option<String>,
+}
+
+impl Commit {
+    /// Create a Commit at current time.
+    /// example:
+    /// ```ignore
+    /// let patch_hash = (new - old).write_to_store(&store)?;
+    /// let commit = Commit::new(patch_hash, Some(parent_hash), s!("eq99"), s!("fix typo"));
+    /// let commit_hash = commit.write_to_store(&store)?;
+    /// ```
+    pub fn new(patch: String, parent: Option<String>, author: String, message: String) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        Self {
+            patch,
+            parent,
+            author,
+            timestamp,
+            message,
+            snapshot: None,
+        }
+    }
+
+    /// Encode Commit to text, the format is like git:
+    /// ```text
+    /// commit 1
+    /// patch <patch hash>
+    /// parent <parent hash>
+    /// author <author>
+    /// timestamp <timestamp>
+    /// snapshot <document hash>
+    ///
+    /// <message>
+    /// ```
+    /// The parent line is omitted for the first commit,
+    /// the snapshot line is omitted if there is no snapshot.
+    pub fn encode(&self) -> String {
+        let mut content = format!("commit {}\npatch {}\n", COMMIT_VERSION, self.patch);
+        if let Some(parent) = &self.parent {
+            content.push_str(&format!("parent {}\n", parent));
+        }
+        content.push_str(&format!(
+            "author {}\ntimestamp {}\n",
+            self.author, self.timestamp
+        ));
+        if let Some(snapshot) = &self.snapshot {
+            content.push_str(&format!("snapshot {}\n", snapshot));
+        }
+        content.push('\n');
+        content.push_str(&self.message);
+        content
+    }
+
+    /// Decode Commit from text, see `Commit::encode` for the format.
+    pub fn decode(content: &str) -> Result<Self> {
+        let invalid = |msg: String| Error::new(ErrorKind::InvalidData, msg);
+        let (headers, message) = content
+            .split_once("\n\n")
+            .ok_or_else(|| invalid(s!("commit message is missing")))?;
+
+        let mut version = None;
+        let mut patch = None;
+        let mut parent = None;
+        let mut author = None;
+        let mut timestamp = None;
+        let mut snapshot = None;
+        for line in headers.split('\n') {
+            let (key, value) = line
+                .split_once(' ')
+                .ok_or_else(|| invalid(format!("invalid commit header: {:?}", line)))?;
+            let field = match key {
+                "commit" => &mut version,
+                "patch" => &mut patch,
+                "parent" => &mut parent,
+                "author" => &mut author,
+                "timestamp" => &mut timestamp,
+                "snapshot" => &mut snapshot,
+                _ => return Err(invalid(format!("unknown commit header: {:?}", key))),
+            };
+            if field.replace(s!(value)).is_some() {
+                return Err(invalid(format!("duplicated commit header: {:?}", key)));
+            }
+        }
+
+        if version != Some(COMMIT_VERSION.to_string()) {
+            return Err(invalid(format!(
+                "unsupported commit version: {:?}",
+                version
+            )));
+        }
+        let timestamp = timestamp
+            .and_then(|t| t.parse().ok())
+            .ok_or_else(|| invalid(s!("invalid commit timestamp")))?;
+        Ok(Self {
+            patch: patch.ok_or_else(|| invalid(s!("commit patch is missing")))?,
+            parent,
+            author: author.ok_or_else(|| invalid(s!("commit author is missing")))?,
+            timestamp,
+            message: s!(message),
+            snapshot,
+        })
+    }
+
+    /// Write Commit to store, return the hash.
+    pub fn write_to_store(&self, store: &ObjectStore) -> Result<String> {
+        if self.author.contains('\n') {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "author should be a single line",
+            ));
+        }
+        store.write(self.encode().as_bytes())
+    }
+
+    /// Create a Commit from store.
+    pub fn read_from_store(store: &ObjectStore, hash: String) -> Result<Self> {
+        let content = store.read(&hash)?;
+        let content =
+            String::from_utf8(content).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        Self::decode(&content)
+    }
+
+    /// Walk the history from a commit back to the first commit,
+    /// return the hashes and commits, the latest first.
+    pub fn log(store: &ObjectStore, hash: String) -> Result<Vec<(String, Self)>> {
+        let mut history = vec![];
+        let mut cursor = Some(hash);
+        while let Some(hash) = cursor {
+            let commit = Self::read_from_store(store, hash.clone())?;
+            cursor = commit.parent.clone();
+            history.push((hash, commit));
+        }
+        Ok(history)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use self::super::*;
+    use crate::document::Document;
+    use crate::patch::Patch;
+
+    #[test]
+    // #[ignore]
+    fn test_encode() {
+        let commit = Commit {
+            patch: s!("p"),
+            parent: None,
+            author: s!("eq99"),
+            timestamp: 1,
+            message: s!("first\n\nline"),
+            snapshot: None,
+        };
+        assert_eq!(
+            s!("commit 1\npatch p\nauthor eq99\ntimestamp 1\n\nfirst\n\nline"),
+            commit.encode()
+        );
+        assert_eq!(commit, Commit::decode(&commit.encode()).unwrap());
+
+        let commit = Commit {
+            parent: Some(s!("c")),
+            message: s!(""),
+            snapshot: Some(s!("d")),
+            ..commit
+        };
+        assert_eq!(
+            s!("commit 1\npatch p\nparent c\nauthor eq99\ntimestamp 1\nsnapshot d\n\n"),
+            commit.encode()
+        );
+        assert_eq!(commit, Commit::decode(&commit.encode()).unwrap());
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_decode_invalid() {
+        for content in &[
+            "",
+            "commit 1\npatch p\nauthor a\ntimestamp 1",
+            "commit 2\npatch p\nauthor a\ntimestamp 1\n\n",
+            "commit 1\nauthor a\ntimestamp 1\n\n",
+            "commit 1\npatch p\nauthor a\ntimestamp x\n\n",
+            "commit 1\npatch p\npatch p\nauthor a\ntimestamp 1\n\n",
+            "commit 1\npatch p\nauthor a\ntimestamp 1\ntag t\n\n",
+        ] {
+            let err = Commit::decode(content).unwrap_err();
+            assert_eq!(ErrorKind::InvalidData, err.kind());
+        }
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ObjectStore::new(dir.path());
+        let blank = Document::from_fs(s!("tests/blank"));
+        let base = Document::from_fs(s!("tests/base"));
+        let new = Document::from_fs(s!("tests/change_one"));
+
+        let p1 = (base.clone() - blank.clone())
+            .write_to_store(&store)
+            .unwrap();
+        let c1 = Commit::new(p1, None, s!("eq99"), s!("create"));
+        let c1_hash = c1.write_to_store(&store).unwrap();
+        let p2 = (new.clone() - base).write_to_store(&store).unwrap();
+        let c2 = Commit::new(p2, Some(c1_hash.clone()), s!("eq99"), s!("edit"));
+        let c2_hash = c2.write_to_store(&store).unwrap();
+
+        let history = Commit::log(&store, c2_hash.clone()).unwrap();
+        assert_eq!(vec![(c2_hash, c2), (c1_hash, c1)], history);
+
+        // v0 + p1 + p2 = v2
+        let doc = history.iter().rev().fold(blank, |doc, (_, commit)| {
+            doc + Patch::read_from_store(&store, commit.patch.clone()).unwrap()
+        });
+        assert_eq!(new, doc);
+
+        let commit = Commit::new(s!("p"), None, s!("a\nb"), s!(""));
+        let err = commit.write_to_store(&store).unwrap_err();
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+    }
+}
@@ -0,0 +1,888 @@
+//! This mod implements the line based diff algorithms.
+//! The algorithms work on two slices of lines and find their common parts,
+//! then the common parts are turned into Changes.
+//!
+//! See the paper for more details: http://www.xmailserver.org/diff2.pdf
+
+use std::collections::HashMap;
+
+use crate::patch::Change;
+
+/// A common part of two documents.
+/// 0. start line number of common part in old document
+/// 1. start line number of common part in new document
+/// 2. line count of the common part
+pub type Common = (usize, usize, usize);
+
+/// Find the common parts of two documents with Myers' O(ND) algorithm.
+/// The returned commons are sorted and they form a longest common subsequence,
+/// so the Changes constructed from them are minimal.
+///
+/// The editor matrix has `old.len()` columns and `new.len()` rows,
+/// a move to right removes an old line, a move to down adds a new line,
+/// and a diagonal move keeps a common line.
+/// For each edit distance `d` we record the furthest reaching x on every diagonal `k = x - y`,
+/// the trace of these records is walked backward to find the diagonals (snakes).
+/// Round `d` only reads the diagonals `-d - 1..=d + 1` of the previous round,
+/// so only this window is kept in the trace, the memory cost is O(D^2).
+pub fn myers(old: &[String], new: &[String]) -> Vec<Common> {
+    let n = old.len() as isize;
+    let m = new.len() as isize;
+    let max = n + m;
+    let offset = max + 1;
+    let mut v = vec![0isize; 2 * offset as usize + 1];
+    let mut trace = vec![];
+
+    // step 1: walk forward until we reach the bottom right corner.
+    'outer: for d in 0..=max {
+        trace.push(v[(offset - d - 1) as usize..=(offset + d + 1) as usize].to_vec());
+        for k in (-d..=d).step_by(2) {
+            let idx = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) {
+                v[idx + 1] // move down
+            } else {
+                v[idx - 1] + 1 // move right
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[idx] = x;
+            if x >= n && y >= m {
+                break 'outer;
+            }
+        }
+    }
+
+    // step 2: walk backward and collect the diagonals.
+    let mut matches = vec![];
+    let mut x = n;
+    let mut y = m;
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        // the window of round d starts at diagonal -d - 1
+        let at = |k: isize| v[(k + d + 1) as usize];
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = at(prev_k);
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            matches.push((x as usize, y as usize));
+        }
+        if d > 0 {
+            x = prev_x;
+            y = prev_y;
+        }
+    }
+    matches.reverse();
+    commons_from_matches(&matches)
+}
+
+/// Find the common parts of two documents with the linear space variant of Myers' algorithm.
+/// The result is the same as `myers`, but the trace of the editor matrix is not stored,
+/// so the memory cost is O(N + M) instead of O((N + M) * D).
+///
+/// The idea comes from section 4b of the paper:
+/// search forward from the top left and backward from the bottom right at the same time,
+/// the paths meet at the middle snake which splits the problem into two halves,
+/// then solve the halves recursively.
+pub fn myers_linear(old: &[String], new: &[String]) -> Vec<Common> {
+    let mut matches = vec![];
+    lcs_linear(old, new, 0, 0, None, &mut matches);
+    commons_from_matches(&matches)
+}
+
+/// The same as `myers_linear`, but the search gives up when the edit distance is too large,
+/// then the furthest reaching point is used to split the problem instead of the middle snake.
+/// The result may not be minimal for documents that are very different,
+/// but the time cost is bounded. This idea comes from git.
+pub fn myers_linear_bounded(old: &[String], new: &[String]) -> Vec<Common> {
+    let cost_limit = ((old.len() + new.len()) as f64).sqrt() as isize;
+    let mut matches = vec![];
+    lcs_linear(old, new, 0, 0, Some(cost_limit.max(256)), &mut matches);
+    commons_from_matches(&matches)
+}
+
+/// Collect the matched line pairs of `old` and `new` recursively.
+/// `old_offset` and `new_offset` are the line numbers of the slices in the whole documents.
+fn lcs_linear(
+    old: &[String],
+    new: &[String],
+    old_offset: usize,
+    new_offset: usize,
+    cost_limit: Option<isize>,
+    matches: &mut Vec<(usize, usize)>,
+) {
+    let (prefix, suffix) = strip_common(old, new, old_offset, new_offset, matches);
+    let old_mid = &old[prefix..old.len() - suffix];
+    let new_mid = &new[prefix..new.len() - suffix];
+
+    if !old_mid.is_empty() && !new_mid.is_empty() {
+        let (d, x, y, u, v) = middle_snake(old_mid, new_mid, cost_limit);
+        let old_offset = old_offset + prefix;
+        let new_offset = new_offset + prefix;
+        if d <= 1 {
+            // at most one line is removed or added, the trace is tiny.
+            push_commons(&myers(old_mid, new_mid), old_offset, new_offset, matches);
+        } else {
+            lcs_linear(
+                &old_mid[..x],
+                &new_mid[..y],
+                old_offset,
+                new_offset,
+                cost_limit,
+                matches,
+            );
+            push_commons(&[(x, y, u - x)], old_offset, new_offset, matches);
+            lcs_linear(
+                &old_mid[u..],
+                &new_mid[v..],
+                old_offset + u,
+                new_offset + v,
+                cost_limit,
+                matches,
+            );
+        }
+    }
+
+    let old_tail = old_offset + old.len() - suffix;
+    let new_tail = new_offset + new.len() - suffix;
+    push_commons(&[(old_tail, new_tail, suffix)], 0, 0, matches);
+}
+
+/// Find the middle snake of the editor matrix.
+/// Return (d, x, y, u, v): the edit distance, and the snake from (x, y) to (u, v).
+///
+/// The backward search works on the reversed documents,
+/// a point (x, y) in the backward search is (n - x, m - y) in the editor matrix,
+/// so the forward diagonal `k` and the backward diagonal `delta - k` are the same one.
+///
+/// If `d` exceeds the `cost_limit`, the furthest reaching point of the forward search
+/// is returned as an empty snake.
+fn middle_snake(
+    old: &[String],
+    new: &[String],
+    cost_limit: Option<isize>,
+) -> (usize, usize, usize, usize, usize) {
+    let n = old.len() as isize;
+    let m = new.len() as isize;
+    let delta = n - m;
+    let odd = delta % 2 != 0;
+    let max = (n + m + 1) / 2;
+    let offset = max + 1;
+    let mut forward = vec![0isize; 2 * offset as usize + 1];
+    let mut backward = vec![0isize; 2 * offset as usize + 1];
+
+    for d in 0..=max {
+        // forward search
+        for k in (-d..=d).step_by(2) {
+            let idx = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && forward[idx - 1] < forward[idx + 1]) {
+                forward[idx + 1]
+            } else {
+                forward[idx - 1] + 1
+            };
+            let mut y = x - k;
+            let (x0, y0) = (x, y);
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            forward[idx] = x;
+            let c = delta - k;
+            if odd && c > -d && c < d && x + backward[(c + offset) as usize] >= n {
+                return (
+                    (2 * d - 1) as usize,
+                    x0 as usize,
+                    y0 as usize,
+                    x as usize,
+                    y as usize,
+                );
+            }
+        }
+        // backward search
+        for c in (-d..=d).step_by(2) {
+            let idx = (c + offset) as usize;
+            let mut x = if c == -d || (c != d && backward[idx - 1] < backward[idx + 1]) {
+                backward[idx + 1]
+            } else {
+                backward[idx - 1] + 1
+            };
+            let mut y = x - c;
+            let (x0, y0) = (x, y);
+            while x < n && y < m && old[(n - x - 1) as usize] == new[(m - y - 1) as usize] {
+                x += 1;
+                y += 1;
+            }
+            backward[idx] = x;
+            let k = delta - c;
+            if !odd && k >= -d && k <= d && x + forward[(k + offset) as usize] >= n {
+                return (
+                    (2 * d) as usize,
+                    (n - x) as usize,
+                    (m - y) as usize,
+                    (n - x0) as usize,
+                    (m - y0) as usize,
+                );
+            }
+        }
+        if cost_limit.is_some_and(|limit| d >= limit) {
+            let (x, y) = (-d..=d)
+                .step_by(2)
+                .map(|k| (forward[(k + offset) as usize], k))
+                .map(|(x, k)| (x, x - k))
+                .filter(|&(x, y)| x <= n && y <= m)
+                .max_by_key(|&(x, y)| x + y)
+                .unwrap();
+            return (
+                (2 * d) as usize,
+                x as usize,
+                y as usize,
+                x as usize,
+                y as usize,
+            );
+        }
+    }
+    unreachable!("the forward and backward paths always meet")
+}
+
+/// Find the common parts of two documents with the patience algorithm.
+/// Step 1: find the lines that appear exactly once in both documents.
+/// Step 2: pick the longest increasing subsequence of these unique lines as anchors.
+/// Step 3: diff the parts between anchors recursively,
+/// fall back to Myers' algorithm with the options if there is no unique line.
+///
+/// The anchors are usually headings or code, not blank lines or `---`,
+/// so the changes are easier to read when paragraphs are moved.
+/// See: https://bramcohen.livejournal.com/73318.html
+pub fn patience(old: &[String], new: &[String], options: DiffOptions) -> Vec<Common> {
+    let mut matches = vec![];
+    lcs_patience(old, new, 0, 0, options, &mut matches);
+    commons_from_matches(&matches)
+}
+
+fn lcs_patience(
+    old: &[String],
+    new: &[String],
+    old_offset: usize,
+    new_offset: usize,
+    options: DiffOptions,
+    matches: &mut Vec<(usize, usize)>,
+) {
+    let (prefix, suffix) = strip_common(old, new, old_offset, new_offset, matches);
+    let old_mid = &old[prefix..old.len() - suffix];
+    let new_mid = &new[prefix..new.len() - suffix];
+    let old_offset = old_offset + prefix;
+    let new_offset = new_offset + prefix;
+
+    if !old_mid.is_empty() && !new_mid.is_empty() {
+        // step 1: line -> (count in old, line num in old, count in new, line num in new)
+        let mut counter: HashMap<&str, (usize, usize, usize, usize)> = HashMap::new();
+        for (idx, line) in old_mid.iter().enumerate() {
+            let entry = counter.entry(line).or_insert((0, 0, 0, 0));
+            entry.0 += 1;
+            entry.1 = idx;
+        }
+        for (idx, line) in new_mid.iter().enumerate() {
+            if let Some(entry) = counter.get_mut(line.as_str()) {
+                entry.2 += 1;
+                entry.3 = idx;
+            }
+        }
+        let mut uniques: Vec<(usize, usize)> = counter
+            .values()
+            .filter(|c| c.0 == 1 && c.2 == 1)
+            .map(|c| (c.1, c.3))
+            .collect();
+        uniques.sort_by_key(|u| u.1);
+
+        // step 2, 3
+        let anchors = longest_increasing(&uniques);
+        if anchors.is_empty() {
+            let commons = fallback(old_mid, new_mid, options);
+            push_commons(&commons, old_offset, new_offset, matches);
+        } else {
+            let mut old_line_num = 0;
+            let mut new_line_num = 0;
+            for (old_anchor, new_anchor) in anchors {
+                lcs_patience(
+                    &old_mid[old_line_num..old_anchor],
+                    &new_mid[new_line_num..new_anchor],
+                    old_offset + old_line_num,
+                    new_offset + new_line_num,
+                    options,
+                    matches,
+                );
+                matches.push((old_offset + old_anchor, new_offset + new_anchor));
+                old_line_num = old_anchor + 1;
+                new_line_num = new_anchor + 1;
+            }
+            lcs_patience(
+                &old_mid[old_line_num..],
+                &new_mid[new_line_num..],
+                old_offset + old_line_num,
+                new_offset + new_line_num,
+                options,
+                matches,
+            );
+        }
+    }
+
+    let old_tail = old_offset + old_mid.len();
+    let new_tail = new_offset + new_mid.len();
+    push_commons(&[(old_tail, new_tail, suffix)], 0, 0, matches);
+}
+
+/// Find the longest subsequence of pairs that is increasing on the old line number,
+/// the pairs must be sorted by the new line number.
+/// This is the patience sorting: put each card on the leftmost pile whose top is larger,
+/// and remember the top of the previous pile as its predecessor.
+fn longest_increasing(pairs: &[(usize, usize)]) -> Vec<(usize, usize)> {
+    let mut piles: Vec<usize> = vec![]; // index of the top card of each pile
+    let mut predecessors = vec![None; pairs.len()];
+    for (idx, pair) in pairs.iter().enumerate() {
+        let pile = piles.partition_point(|&top| pairs[top].0 < pair.0);
+        if pile > 0 {
+            predecessors[idx] = Some(piles[pile - 1]);
+        }
+        if pile == piles.len() {
+            piles.push(idx);
+        } else {
+            piles[pile] = idx;
+        }
+    }
+    let mut result = vec![];
+    let mut cursor = piles.last().copied();
+    while let Some(idx) = cursor {
+        result.push(pairs[idx]);
+        cursor = predecessors[idx];
+    }
+    result.reverse();
+    result
+}
+
+/// Lines occur more than this count in old document are not used as anchors in histogram diff.
+const MAX_CHAIN_LENGTH: usize = 64;
+
+/// The histogram diff scans at most this many times the total line count of two documents,
+/// the parts left after that fall back to Myers' algorithm.
+const MAX_SCAN_FACTOR: usize = 32;
+
+/// Find the common parts of two documents with the histogram algorithm.
+/// It's an extension of patience algorithm, it comes from jgit:
+/// Step 1: count the occurrences of each line in old document.
+/// Step 2: for each line of new document that also appears in old document,
+/// extend it to the longest common region around it,
+/// pick the region whose anchor line occurs the least times, then the longer one.
+/// Step 3: diff the parts before and after the region with a stack of parts,
+/// fall back to Myers' algorithm with the options if there is no such region,
+/// or the scan limit is reached.
+pub fn histogram(old: &[String], new: &[String], options: DiffOptions) -> Vec<Common> {
+    let mut matches = vec![];
+    let scan_limit = MAX_SCAN_FACTOR * (old.len() + new.len());
+    let mut scanned = 0;
+    // the line ranges [start, end) of the parts in old and new documents
+    let mut parts = vec![((0, old.len()), (0, new.len()))];
+    while let Some(((old_start, old_end), (new_start, new_end))) = parts.pop() {
+        let old_part = &old[old_start..old_end];
+        let new_part = &new[new_start..new_end];
+        let (prefix, suffix) = strip_common(old_part, new_part, old_start, new_start, &mut matches);
+        push_commons(
+            &[(old_end - suffix, new_end - suffix, suffix)],
+            0,
+            0,
+            &mut matches,
+        );
+        let old_mid = &old_part[prefix..old_part.len() - suffix];
+        let new_mid = &new_part[prefix..new_part.len() - suffix];
+        let old_offset = old_start + prefix;
+        let new_offset = new_start + prefix;
+        if old_mid.is_empty() || new_mid.is_empty() {
+            continue;
+        }
+
+        scanned += old_mid.len() + new_mid.len();
+        let region = if scanned <= scan_limit {
+            least_frequent_region(old_mid, new_mid)
+        } else {
+            None
+        };
+        match region {
+            Some((old_line_num, new_line_num, common_line_count)) => {
+                push_commons(
+                    &[(old_line_num, new_line_num, common_line_count)],
+                    old_offset,
+                    new_offset,
+                    &mut matches,
+                );
+                parts.push((
+                    (
+                        old_offset + old_line_num + common_line_count,
+                        old_offset + old_mid.len(),
+                    ),
+                    (
+                        new_offset + new_line_num + common_line_count,
+                        new_offset + new_mid.len(),
+                    ),
+                ));
+                parts.push((
+                    (old_offset, old_offset + old_line_num),
+                    (new_offset, new_offset + new_line_num),
+                ));
+            }
+            None => {
+                let commons = fallback(old_mid, new_mid, options);
+                push_commons(&commons, old_offset, new_offset, &mut matches);
+            }
+        }
+    }
+    // the parts are done out of order
+    matches.sort_unstable();
+    commons_from_matches(&matches)
+}
+
+/// Step 1 and 2 of `histogram`, return None if no line is common or every line is too frequent.
+/// Like jgit, the scan of new document skips the lines covered by the regions found,
+/// and the occurrences covered by the previous region in old document.
+fn least_frequent_region(old: &[String], new: &[String]) -> Option<Common> {
+    // step 1
+    let mut occurrences: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (idx, line) in old.iter().enumerate() {
+        occurrences.entry(line).or_default().push(idx);
+    }
+
+    // step 2: (occurrence count, region)
+    let mut best: Option<(usize, Common)> = None;
+    let mut new_line_num = 0;
+    while new_line_num < new.len() {
+        let mut next = new_line_num + 1;
+        let positions = match occurrences.get(new[new_line_num].as_str()) {
+            Some(positions) if positions.len() <= MAX_CHAIN_LENGTH => positions,
+            _ => {
+                new_line_num = next;
+                continue;
+            }
+        };
+        if matches!(best, Some((count, _)) if positions.len() > count) {
+            new_line_num = next;
+            continue;
+        }
+        // the end of the previous region in old document
+        let mut old_end = 0;
+        for &old_line_num in positions {
+            if old_line_num < old_end {
+                continue;
+            }
+            let before = old[..old_line_num]
+                .iter()
+                .rev()
+                .zip(new[..new_line_num].iter().rev())
+                .take_while(|(a, b)| a == b)
+                .count();
+            let after = old[old_line_num..]
+                .iter()
+                .zip(&new[new_line_num..])
+                .take_while(|(a, b)| a == b)
+                .count();
+            old_end = old_line_num + after;
+            next = next.max(new_line_num + after);
+            let region = (old_line_num - before, new_line_num - before, before + after);
+            let better = match best {
+                Some((count, c)) => {
+                    positions.len() < count || (positions.len() == count && region.2 > c.2)
+                }
+                None => true,
+            };
+            if better {
+                best = Some((positions.len(), region));
+            }
+        }
+        new_line_num = next;
+    }
+    best.map(|(_, region)| region)
+}
+
+/// Diff the parts without anchors with Myers' algorithm,
+/// the linear space variant is still selected by the options.
+fn fallback(old: &[String], new: &[String], options: DiffOptions) -> Vec<Common> {
+    let options = DiffOptions {
+        algorithm: DiffAlgorithm::Myers,
+        ..options
+    };
+    diff(old, new, options)
+}
+
+/// Push the matched line pairs of the common prefix of two documents,
+/// return the line count of common prefix and suffix.
+/// The suffix is not pushed, because the matches must be sorted,
+/// the caller should push it after the middle part.
+fn strip_common(
+    old: &[String],
+    new: &[String],
+    old_offset: usize,
+    new_offset: usize,
+    matches: &mut Vec<(usize, usize)>,
+) -> (usize, usize) {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    push_commons(&[(old_offset, new_offset, prefix)], 0, 0, matches);
+    (prefix, suffix)
+}
+
+/// Push the matched line pairs of common parts,
+/// the line numbers of common parts are relative to the offsets.
+fn push_commons(
+    commons: &[Common],
+    old_offset: usize,
+    new_offset: usize,
+    matches: &mut Vec<(usize, usize)>,
+) {
+    for &(old_line_num, new_line_num, common_line_count) in commons {
+        for idx in 0..common_line_count {
+            matches.push((
+                old_offset + old_line_num + idx,
+                new_offset + new_line_num + idx,
+            ));
+        }
+    }
+}
+
+/// The diff algorithms, see the comparison of git's diff algorithms:
+/// https://luppeng.wordpress.com/2020/10/10/when-to-use-each-of-the-git-diff-algorithms/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffAlgorithm {
+    /// Myers' algorithm, it may give up the minimal result for very large and different documents.
+    Myers,
+    /// Myers' algorithm, the result is always minimal.
+    Minimal,
+    /// The patience algorithm, anchored on unique lines.
+    Patience,
+    /// The histogram algorithm, anchored on the least frequent lines.
+    Histogram,
+}
+
+/// The options to diff two documents.
+/// The default is Myers, which gives up the minimal result for documents larger than
+/// `linear_space_threshold` when the edit distance is too large, see `myers_linear_bounded`.
+/// Use Minimal to always get a longest common subsequence.
+#[derive(Debug, Clone, Copy)]
+pub struct DiffOptions {
+    pub algorithm: DiffAlgorithm,
+    /// Use the linear space algorithm when the total line count of two documents
+    /// is larger than this threshold, it works for Myers and Minimal.
+    /// The trace of `myers` has about D^2 entries, and the edit distance D is up to N + M,
+    /// so the worst case at the threshold is `threshold^2 * 8` bytes.
+    pub linear_space_threshold: usize,
+}
+
+impl Default for DiffOptions {
+    /// The worst case trace at the default threshold takes 32 MB.
+    fn default() -> Self {
+        Self {
+            algorithm: DiffAlgorithm::Myers,
+            linear_space_threshold: 2_000,
+        }
+    }
+}
+
+/// Find the common parts of two documents with the algorithm in options.
+/// For Myers and Minimal, the linear space variant is selected by the size of documents.
+pub fn diff(old: &[String], new: &[String], options: DiffOptions) -> Vec<Common> {
+    let large = old.len() + new.len() > options.linear_space_threshold;
+    match options.algorithm {
+        DiffAlgorithm::Myers if large => myers_linear_bounded(old, new),
+        DiffAlgorithm::Minimal if large => myers_linear(old, new),
+        DiffAlgorithm::Myers | DiffAlgorithm::Minimal => myers(old, new),
+        DiffAlgorithm::Patience => patience(old, new, options),
+        DiffAlgorithm::Histogram => histogram(old, new, options),
+    }
+}
+
+/// Group the matched line pairs into common parts.
+/// The matched pairs must be sorted on both old and new line numbers.
+pub fn commons_from_matches(matches: &[(usize, usize)]) -> Vec<Common> {
+    let mut commons: Vec<Common> = vec![];
+    for &(old_line_num, new_line_num) in matches {
+        match commons.last_mut() {
+            Some(c) if c.0 + c.2 == old_line_num && c.1 + c.2 == new_line_num => c.2 += 1,
+            _ => commons.push((old_line_num, new_line_num, 1)),
+        }
+    }
+    commons
+}
+
+/// Construct the changes between the common parts.
+/// 0<-change->commons[0]<-change->commons[1]<-change->...commons_last<-change->end
+///
+/// Before you read the following code, make sure you have read the doc for type Change in patch.rs
+pub fn changes_from_commons(old: &[String], new: &[String], commons: &[Common]) -> Vec<Change> {
+    let mut changes: Vec<Change> = vec![];
+    let mut old_line_num = 0;
+    let mut new_line_num = 0;
+    let tail = (old.len(), new.len(), 0);
+    for &(old_end, new_end, common_line_count) in commons.iter().chain(Some(&tail)) {
+        if old_end > old_line_num || new_end > new_line_num {
+            changes.push((
+                old_line_num,
+                old_end - old_line_num,
+                new_line_num,
+                new_end - new_line_num,
+                old[old_line_num..old_end].to_vec(),
+                new[new_line_num..new_end].to_vec(),
+            ));
+        }
+        old_line_num = old_end + common_line_count;
+        new_line_num = new_end + common_line_count;
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use self::super::*;
+    use crate::macros::s;
+
+    fn lines(s: &str) -> Vec<String> {
+        s.chars().map(|c| c.to_string()).collect()
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_myers_paper_example() {
+        // The example in Fig 1. of the paper, the edit distance is 5.
+        let old = lines("ABCABBA");
+        let new = lines("CBABAC");
+        let commons = myers(&old, &new);
+        let common_line_count: usize = commons.iter().map(|c| c.2).sum();
+        assert_eq!(4, common_line_count);
+
+        let changes = changes_from_commons(&old, &new, &commons);
+        let edit_distance: usize = changes.iter().map(|c| c.1 + c.3).sum();
+        assert_eq!(5, edit_distance);
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_myers_same() {
+        let old = lines("ABC");
+        assert_eq!(vec![(0, 0, 3)], myers(&old, &old));
+        assert!(changes_from_commons(&old, &old, &myers(&old, &old)).is_empty());
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_myers_empty() {
+        let old = lines("ABC");
+        let new = vec![];
+        assert!(myers(&old, &new).is_empty());
+        assert_eq!(
+            vec![(0, 3, 0, 0, old.clone(), vec![])],
+            changes_from_commons(&old, &new, &[])
+        );
+        assert!(myers(&new, &new).is_empty());
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_myers_unrelated() {
+        // nothing in common, the edit distance is the total line count.
+        let old: Vec<String> = (0..1_000).map(|i| format!("old {}", i)).collect();
+        let new: Vec<String> = (0..1_000).map(|i| format!("new {}", i)).collect();
+        assert!(myers(&old, &new).is_empty());
+
+        let old = lines("XABCY");
+        let new = lines("ZABCW");
+        assert_eq!(vec![(1, 1, 3)], myers(&old, &new));
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_changes_from_commons() {
+        let old = lines("ABCDEF");
+        let new = lines("ABXYDEF");
+        let commons = myers(&old, &new);
+        assert_eq!(vec![(0, 0, 2), (3, 4, 3)], commons);
+        assert_eq!(
+            vec![(2, 1, 2, 2, vec![s!("C")], vec![s!("X"), s!("Y")])],
+            changes_from_commons(&old, &new, &commons)
+        );
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_myers_linear() {
+        let old = lines("ABCABBA");
+        let new = lines("CBABAC");
+        let commons = myers_linear(&old, &new);
+        let common_line_count: usize = commons.iter().map(|c| c.2).sum();
+        assert_eq!(4, common_line_count);
+
+        let old = lines("ABCDEF");
+        let new = lines("ABXYDEF");
+        assert_eq!(myers(&old, &new), myers_linear(&old, &new));
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_linear_space_threshold() {
+        // nothing in common at the threshold, the worst case of the trace.
+        let old: Vec<String> = (0..1_000).map(|i| format!("old {}", i)).collect();
+        let new: Vec<String> = (0..1_001).map(|i| format!("new {}", i)).collect();
+        assert_eq!(2_000, DiffOptions::default().linear_space_threshold);
+        for algorithm in [DiffAlgorithm::Myers, DiffAlgorithm::Minimal] {
+            let options = DiffOptions {
+                algorithm,
+                ..DiffOptions::default()
+            };
+            assert!(diff(&old, &new[..1_000], options).is_empty());
+            // just over the threshold, the linear space variant is used.
+            assert!(diff(&old, &new, options).is_empty());
+        }
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_myers_linear_large() {
+        // every 10th line is changed, the trace of myers would take gigabytes here.
+        let old: Vec<String> = (0..20_000).map(|i| format!("line {}", i)).collect();
+        let new: Vec<String> = (0..20_000)
+            .map(|i| {
+                if i % 10 == 0 {
+                    format!("changed {}", i)
+                } else {
+                    format!("line {}", i)
+                }
+            })
+            .collect();
+        let commons = diff(&old, &new, DiffOptions::default());
+        let common_line_count: usize = commons.iter().map(|c| c.2).sum();
+        assert_eq!(18_000, common_line_count);
+        assert_eq!(2_000, changes_from_commons(&old, &new, &commons).len());
+    }
+
+    fn markdown(s: &str) -> Vec<String> {
+        s.lines().map(String::from).collect()
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_reorganise_sections() {
+        // section B is moved before section A.
+        let old = markdown("# A\n\nalpha\n\n---\n\n# B\n\nbeta\n\n---\n");
+        let new = markdown("# B\n\nbeta\n\n---\n\n# A\n\nalpha\n\n---\n");
+
+        // Myers only keeps blank lines and separators, every heading is changed.
+        let commons = myers(&old, &new);
+        for (old_line_num, _, common_line_count) in commons {
+            for line in &old[old_line_num..old_line_num + common_line_count] {
+                assert!(line.is_empty() || line == "---");
+            }
+        }
+        assert_eq!(
+            4,
+            changes_from_commons(&old, &new, &myers(&old, &new)).len()
+        );
+
+        // Patience and histogram keep a whole section.
+        let options = DiffOptions::default();
+        for commons in &[
+            patience(&old, &new, options),
+            histogram(&old, &new, options),
+        ] {
+            let changes = changes_from_commons(&old, &new, commons);
+            assert_eq!(2, changes.len());
+            assert!(commons.iter().any(|c| c.2 >= 3 && !old[c.0].is_empty()));
+        }
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_patience_unique_lines() {
+        let old = lines("ABCADE");
+        let new = lines("AXBCAD");
+        let commons = patience(&old, &new, DiffOptions::default());
+        // B, C, D are unique, the repeated A is matched between anchors.
+        assert_eq!(vec![(0, 0, 1), (1, 2, 4)], commons);
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_fallback_options() {
+        // no unique line, the fallback takes the linear space variant of the options.
+        let old: Vec<String> = (0..3_000).map(|i| s!(["a", "b"][i % 2])).collect();
+        let new: Vec<String> = (0..3_000).map(|i| s!(["b", "a"][i % 2])).collect();
+        let options = DiffOptions::default();
+        let expected = myers_linear_bounded(&old, &new);
+        assert_eq!(expected, patience(&old, &new, options));
+        assert_eq!(expected, histogram(&old, &new, options));
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_histogram_large() {
+        // only the first and last lines are changed.
+        let old: Vec<String> = (0..20_000).map(|i| format!("line {}", i)).collect();
+        let mut new = old.clone();
+        new[0] = s!("first");
+        new[19_999] = s!("last");
+        let options = DiffOptions::default();
+        assert_eq!(vec![(1, 1, 19_998)], histogram(&old, &new, options));
+
+        // every other line is changed, there are thousands of regions.
+        let old: Vec<String> = (0..20_000).map(|i| format!("line {}", i)).collect();
+        let new: Vec<String> = (0..20_000)
+            .map(|i| {
+                if i % 2 == 0 {
+                    format!("changed {}", i)
+                } else {
+                    format!("line {}", i)
+                }
+            })
+            .collect();
+        let commons = histogram(&old, &new, options);
+        for &(old_line_num, new_line_num, common_line_count) in &commons {
+            assert_eq!(
+                old[old_line_num..old_line_num + common_line_count],
+                new[new_line_num..new_line_num + common_line_count]
+            );
+        }
+        let common_line_count: usize = commons.iter().map(|c| c.2).sum();
+        assert_eq!(10_000, common_line_count);
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_longest_increasing() {
+        let pairs = vec![(9, 0), (4, 1), (6, 2), (2, 3), (7, 4), (8, 5), (3, 6)];
+        assert_eq!(
+            vec![(4, 1), (6, 2), (7, 4), (8, 5)],
+            longest_increasing(&pairs)
+        );
+        assert!(longest_increasing(&[]).is_empty());
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_myers_linear_bounded() {
+        // nothing in common, the cost limit is reached.
+        let old: Vec<String> = (0..2_000).map(|i| format!("old {}", i)).collect();
+        let new: Vec<String> = (0..2_000).map(|i| format!("new {}", i)).collect();
+        assert!(myers_linear_bounded(&old, &new).is_empty());
+
+        let old = lines("ABCABBA");
+        let new = lines("CBABAC");
+        assert_eq!(myers_linear(&old, &new), myers_linear_bounded(&old, &new));
+    }
+}
@@ -3,16 +3,22 @@
 use std::fmt;
 use std::fs;
 use std::fs::File;
-use std::io::{BufRead, BufReader, Error, Write};
+use std::io::{BufRead, BufReader, Error, ErrorKind, Result, Write};
 use std::ops::{Add, Sub};
 
+use crate::diff::{self, DiffAlgorithm, DiffOptions};
+use crate::fuzzy::{self, ContextPatch, FuzzyOptions, FuzzyResult};
 use crate::macros::s;
+use crate::merge::{self, MergeResult};
 use crate::patch::{Change, Patch};
+use crate::store::ObjectStore;
 
 /// The Document struct reprents a real word document.
 /// Because operations are line based, We use a Vec<String> to store lines.
-#[derive(Debug, Clone)]
-pub struct Document(Vec<String>);
+/// 0. lines without the line separator `\n`
+/// 1. true if the last line doesn't end with `\n`, it's always false for an empty document
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Document(Vec<String>, bool);
 
 impl Document {
     /// Create a Document object from file.
@@ -22,12 +28,51 @@ impl Document {
     /// assert_eq!(s!("A\nB\nC\nD\nE\nF\n"), doc.to_string());
     /// ```
     pub fn from_fs(file: String) -> Self {
-        let input = File::open(file).unwrap();
-        let lines_vec: Vec<_> = BufReader::new(input)
-            .lines()
-            .map(|result| result.unwrap())
-            .collect();
-        Self(lines_vec)
+        Self::from_content(&fs::read_to_string(file).unwrap())
+    }
+
+    /// Create a Document object from lines, the last line ends with `\n`.
+    pub fn from_vec(vec: Vec<String>) -> Self {
+        Self(vec, false)
+    }
+
+    /// Create a Document object from content.
+    /// Lines are split by `\n`, a `\r` before `\n` is kept in the line,
+    /// so `Document::from_content(content).to_content() == content`.
+    pub fn from_content(content: &str) -> Self {
+        let no_newline_at_eof = !content.is_empty() && !content.ends_with('\n');
+        let mut lines_vec: Vec<_> = content.split('\n').map(String::from).collect();
+        if !no_newline_at_eof {
+            // the empty string after the last `\n`
+            lines_vec.pop();
+        }
+        Self(lines_vec, no_newline_at_eof)
+    }
+
+    /// Convert the Document to its original content,
+    /// the last line ends with `\n` unless the original content doesn't.
+    /// example:
+    /// ```ignore
+    /// let doc = Document::from_fs(s!("tests/base"));
+    /// assert_eq!(s!("A\nB\nC\nD\nE\nF"), doc.to_content());
+    /// ```
+    pub fn to_content(&self) -> String {
+        let mut content = self.as_vec_ref().join("\n");
+        if !self.as_vec_ref().is_empty() && !self.no_newline_at_eof() {
+            content.push('\n');
+        }
+        content
+    }
+
+    /// Whether the last line doesn't end with `\n`.
+    pub fn no_newline_at_eof(&self) -> bool {
+        self.1
+    }
+
+    /// Set whether the last line doesn't end with `\n`, it's ignored for an empty document.
+    pub fn with_no_newline_at_eof(self, no_newline_at_eof: bool) -> Self {
+        let no_newline_at_eof = no_newline_at_eof && !self.0.is_empty();
+        Self(self.0, no_newline_at_eof)
     }
 
     /// Display lines with number.
@@ -43,7 +88,7 @@ impl Document {
     /// 4. E
     /// 5. F
     pub fn show(&self) {
-        let Document(lines) = self;
+        let Document(lines, _) = self;
         for line in lines.iter().enumerate() {
             println!("{}. {}", line.0, line.1);
         }
@@ -59,242 +104,291 @@ impl Document {
     /// }
     /// ```
     pub fn as_vec_ref(&self) -> &Vec<String> {
-        let Self(vec) = self;
+        let Self(vec, _) = self;
         vec
     }
 
-    /// Convet vec to string
+    /// Write Document to file system as a blob, return the hash of blob.
+    /// Step 1: calculate content sha-256 hash
+    /// Step 2: use first two hex code as folder, the rest as file name.
+    /// This idea comes from git.
     /// example:
     /// ```ignore
-    /// let doc = Document::from_fs(s!("tests/base"));
-    /// assert_eq!(s!("A\nB\nC\nD\nE\nF\n"), doc.to_string());
+    /// let store = ObjectStore::new("objects");
+    /// let hash = doc.write_to_store(&store)?;
+    /// assert_eq!(doc, Document::read_from_store(&store, hash)?);
     /// ```
-    pub fn to_string(&self) -> String {
-        self.as_vec_ref()
-            .iter()
-            .fold(String::new(), |s, line| format!("{}{}\n", s, line))
+    pub fn write_to_store(&self, store: &ObjectStore) -> Result<String> {
+        store.write(self.to_content().as_bytes())
     }
 
-    /// Write Document to file system.
-    /// Step 1: calculate content sha-256 hash
-    /// Step 2: use first two hex code as folder, the rest as file name.
-    /// This idea comes from git.
-    pub fn write_to_store(&self) {
-        // let data = "Some data!";
-        // fs::write("/tmp/foo", data).expect("Unable to write file");
-        todo!()
+    /// Create a Document from store.
+    pub fn read_from_store(store: &ObjectStore, hash: String) -> Result<Self> {
+        let content = store.read(&hash)?;
+        let content =
+            String::from_utf8(content).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        Ok(Self::from_content(&content))
     }
 
-    /// Create a Document from store.
-    pub fn read_from_store(hash: String) -> Self {
-        // let lines = vec![];
-        // Self(lines)
-        todo!()
+    /// Three-way merge, ours and theirs are both edited from base.
+    /// The Changes of two sides are merged if they don't overlap or touch,
+    /// otherwise the conflicts are reported in MergeResult.
+    /// example:
+    /// ```ignore
+    /// let result = Document::merge3(&v0, &v1_1, &v1_2);
+    /// match result.document() {
+    ///     Some(v2) => v2.show(),
+    ///     None => println!("{:?}", result.conflicts()),
+    /// }
+    /// ```
+    pub fn merge3(base: &Self, ours: &Self, theirs: &Self) -> MergeResult {
+        merge::merge3(base, ours, theirs)
     }
-}
- 
-impl Sub for Document {
-    type Output = Patch;
-    /// Diff of two document
-    /// algorithm notes:
-    /// Step 1: find all the common parts of two documents.
-    /// 1. compare all sub string of old string with new string.
-    /// 2. compare all sub string of new string with old string symmetrically.
-    ///
-    /// Step 2: Choose the right common parts greedily by common_line_count.
-    ///
-    /// Step 3: Construct the changes.
-    ///
-    /// Step 4: return Patch.
-    ///
-    /// If you don't kown the edtor matrix,
-    /// You can read paper: http://www.xmailserver.org/diff2.pdf
-    /// The Fig 1. will help you a lot.
-    ///
-    /// @param{self}: the new document
-    /// @param{other}: the old document
-    fn sub(self, other: Self) -> Self::Output {
+
+    /// Diff self (the new document) with other (the old document),
+    /// `new.diff_with(&old, options)` is the same as `new - old` with default options.
+    /// example:
+    /// ```ignore
+    /// let options = DiffOptions {
+    ///     algorithm: DiffAlgorithm::Patience,
+    ///     ..DiffOptions::default()
+    /// };
+    /// let patch = new.diff_with(&old, options);
+    /// ```
+    pub fn diff_with(&self, other: &Self, options: DiffOptions) -> Patch {
         let this_vec_ref = self.as_vec_ref();
         let other_vec_ref = other.as_vec_ref();
 
-        // step 1: Find common parts
-        let mut commons = vec![];
-        let mut common_line_count = 0;
-        let mut this_line_num = 0;
-        let mut other_line_num = 0;
-        let mut other_lines_count = other_vec_ref.len();
-        let mut this_lines_count = this_vec_ref.len();
-        // diagonal and upper
-        for i in 0..this_lines_count {
-            for j in 0..other_lines_count {
-                if i + j < this_lines_count
-                    && (this_vec_ref[i + j].len() == other_vec_ref[j].len()
-                        && this_vec_ref[i + j] == other_vec_ref[j])
-                {
-                    if common_line_count == 0 {
-                        this_line_num = i + j;
-                        other_line_num = j;
-                    }
-                    common_line_count += 1;
-                } else {
-                    if common_line_count > 0 {
-                        commons.push((other_line_num, this_line_num, common_line_count));
-                        common_line_count = 0;
-                    }
-                }
+        let commons = diff::diff(other_vec_ref, this_vec_ref, options);
+        let changes = diff::changes_from_commons(other_vec_ref, this_vec_ref, &commons);
+        let no_newline_at_eof = if self.no_newline_at_eof() != other.no_newline_at_eof() {
+            Some(self.no_newline_at_eof())
+        } else {
+            None
+        };
+        Patch::from_vec(changes).with_no_newline_at_eof(no_newline_at_eof)
+    }
+
+    /// Apply patch to self like `self + patch`, but the Patch is verified first:
+    /// each Change is in order, its line numbers follow the previous Changes,
+    /// and its removed lines match the lines of self.
+    /// example:
+    /// ```ignore
+    /// match doc.apply(&patch) {
+    ///     Ok(new) => new.show(),
+    ///     Err(e) => println!("the patch is not for this document: {}", e),
+    /// }
+    /// ```
+    pub fn apply(&self, patch: &Patch) -> std::result::Result<Self, ApplyError> {
+        let old_vec_ref = self.as_vec_ref();
+        let mut pre_line_num = 0;
+        let mut offset: isize = 0;
+        for (idx, change) in patch.as_vec_ref().iter().enumerate() {
+            let invalid = |message: &str| ApplyError::InvalidChange {
+                change: idx,
+                message: s!(message),
+            };
+            if change.1 != change.4.len() || change.3 != change.5.len() {
+                return Err(invalid("line counts don't match the lines"));
             }
-        }
-        // lower diagonal
-        for i in 1..other_lines_count {
-            for j in 0..this_lines_count {
-                if i + j < other_lines_count
-                    && (this_vec_ref[j].len() == other_vec_ref[i + j].len()
-                        && this_vec_ref[j] == other_vec_ref[i + j])
-                {
-                    if common_line_count == 0 {
-                        this_line_num = j;
-                        other_line_num = i + j;
-                    }
-                    common_line_count += 1;
-                } else {
-                    if common_line_count > 0 {
-                        commons.push((other_line_num, this_line_num, common_line_count));
-                        common_line_count = 0;
-                    }
-                }
+            if change.0 < pre_line_num {
+                return Err(invalid("overlaps the previous change"));
             }
-        }
-
-        // println!("Commons: {:?}", commons);
-
-        // Step 2: pick out the right parts
-        commons.sort_by_key(|c| -(c.2 as i64));
-        let mut picked = vec![];
-        let mut pickable = true;
-        if let Some(c) = commons.get(0) {
-            picked.push(c.clone())
-        }
-        for candidate in commons {
-            for picked in &picked {
-                //  * * * *
-                //  * * * *
-                //  * * * *
-                //         \
-                //          \
-                //           * * * *
-                //           * * * *
-                pickable = ((candidate.0 + candidate.2 - 1 < picked.0
-                && candidate.1 + candidate.2 - 1 < picked.1) // top left
-                || (candidate.0 > picked.0 + picked.2 - 1
-                    && candidate.1 > picked.1 + picked.2 - 1)) // bottom right
-                && pickable;
+            if change.2 as isize != change.0 as isize + offset {
+                return Err(invalid(
+                    "new line number doesn't follow the previous changes",
+                ));
             }
-
-            if pickable {
-                picked.push(candidate);
+            if change.0 + change.1 > old_vec_ref.len() {
+                return Err(ApplyError::OutOfRange {
+                    change: idx,
+                    line_num: change.0 + change.1,
+                    len: old_vec_ref.len(),
+                });
             }
-            pickable = true;
-        }
-        picked.sort_by_key(|c| c.0);
-
-        // println!("Picked: {:?}", picked);
-
-        // step 3,4: construct the changes and return patch
-        // Note:
-        // Before you read the following code, make sure you have read the doc for type Change in patch.rs
-        let mut changes: Vec<Change> = vec![];
-        if picked.len() < 1 {
-            if this_lines_count > 0 {
-                changes = if other_lines_count == 0 {
-                    // create from new file
-                    vec![(0, 0, 0, this_lines_count, vec![], (*this_vec_ref).clone())]
-                } else {
-                    // changed all.
-                    vec![(
-                        0,
-                        other_lines_count,
-                        0,
-                        this_lines_count,
-                        (*other_vec_ref).clone(),
-                        (*this_vec_ref).clone(),
-                    )]
-                };
-                return Patch::from_vec(changes);
-            } else {
-                panic!("new file should not be empty");
+            let actual = &old_vec_ref[change.0..change.0 + change.1];
+            if let Some(i) = (0..change.1).find(|&i| actual[i] != change.4[i]) {
+                return Err(ApplyError::Mismatch {
+                    change: idx,
+                    line_num: change.0 + i,
+                    expected: change.4[i].clone(),
+                    actual: actual[i].clone(),
+                });
             }
+            pre_line_num = change.0 + change.1;
+            offset += change.3 as isize - change.1 as isize;
         }
+        Ok(self.clone() + patch.clone())
+    }
 
-        // 0<-change->picked[0]<-change->picked[1]<-change->...common_last<-change->end
-
-        // case 1:
-        //   0 1 2 3 4 5      0 1 2 3 4 5
-        // 0                0   \
-        // 1\               1    \
-        // 2 \              2     \
-        // 3  \             3      \
-        if picked[0].0 > 0 || picked[0].1 > 0 {
-            let mut removed_lines = vec![];
-            for idx in 0..picked[0].0 {
-                removed_lines.push(other_vec_ref[idx].clone());
-            }
-            let mut added_lines = vec![];
-            for idx in 0..picked[0].1 {
-                added_lines.push(this_vec_ref[idx].clone());
+    /// Apply the Changes of patch selected by index, see `Document::apply_partial`.
+    pub fn apply_selected(
+        &self,
+        patch: &Patch,
+        indexes: &[usize],
+    ) -> std::result::Result<(Self, Patch), ApplyError> {
+        self.apply_partial(patch, |idx, _| indexes.contains(&idx))
+    }
+
+    /// Apply the Changes of patch selected by the predicate, the others are rejected.
+    /// Return the new Document and the residual Patch of rejected Changes against it,
+    /// so that `new + residual == self + patch`.
+    /// The patch is verified like `Document::apply`.
+    /// example:
+    /// ```ignore
+    /// // accept the removals only
+    /// let (new, residual) = doc.apply_partial(&patch, |_, change| change.3 == 0)?;
+    /// ```
+    pub fn apply_partial<F: Fn(usize, &Change) -> bool>(
+        &self,
+        patch: &Patch,
+        select: F,
+    ) -> std::result::Result<(Self, Patch), ApplyError> {
+        self.apply(patch)?;
+        let mut accepted = vec![];
+        let mut rejected = vec![];
+        // the offset of line numbers made by the accepted Changes
+        let mut offset: isize = 0;
+        let mut eof_accepted = true;
+        for (idx, change) in patch.as_vec_ref().iter().enumerate() {
+            let mut change = change.clone();
+            // the new `no_newline_at_eof` state goes with the Change at the end of document
+            let at_eof = change.0 + change.1 == self.as_vec_ref().len();
+            if select(idx, &change) {
+                change.2 = (change.0 as isize + offset) as usize;
+                offset += change.3 as isize - change.1 as isize;
+                accepted.push(change);
+            } else {
+                // the new line number is the same as in patch
+                change.0 = (change.0 as isize + offset) as usize;
+                eof_accepted = !at_eof;
+                rejected.push(change);
             }
-            changes.push((0, picked[0].0, 0, picked[1].1, removed_lines, added_lines));
         }
-        //  \
-        //   \
-        //    \ _ _
-        //         \
-        //          \
-        //           \
-        for idx in 0..picked.len() - 1 {
-            let end_old = picked[idx].0 + picked[idx].2;
-            let end_new = picked[idx].1 + picked[idx].2;
-            if picked[idx + 1].0 > end_old || picked[idx + 1].1 > end_new {
-                let mut removed_lines = vec![];
-                for idx in end_old..picked[idx + 1].0 {
-                    removed_lines.push(other_vec_ref[idx].clone());
-                }
-                let mut added_lines = vec![];
-                for idx in end_new..picked[idx + 1].1 {
-                    added_lines.push(this_vec_ref[idx].clone());
-                }
-                changes.push((
-                    end_old,
-                    picked[idx + 1].0 - end_old,
-                    end_new,
-                    picked[idx + 1].1 - end_new,
-                    removed_lines,
-                    added_lines,
-                ));
+        let (accepted_eof, rejected_eof) = if eof_accepted {
+            (patch.no_newline_at_eof(), None)
+        } else {
+            (None, patch.no_newline_at_eof())
+        };
+        let new = self.clone() + Patch::from_vec(accepted).with_no_newline_at_eof(accepted_eof);
+        let residual = Patch::from_vec(rejected).with_no_newline_at_eof(rejected_eof);
+        Ok((new, residual))
+    }
+
+    /// Apply a ContextPatch to self, which may have drifted from the old document of the Patch,
+    /// see `fuzzy::apply_fuzzy`.
+    /// example:
+    /// ```ignore
+    /// let result = drifted.apply_fuzzy(&patch, FuzzyOptions::default());
+    /// for (idx, placement) in result.hunks.iter().enumerate() {
+    ///     match placement {
+    ///         Some(p) => println!("hunk {} applied with offset {} fuzz {}", idx, p.offset, p.fuzz),
+    ///         None => println!("hunk {} rejected", idx),
+    ///     }
+    /// }
+    /// ```
+    pub fn apply_fuzzy(&self, patch: &ContextPatch, options: FuzzyOptions) -> FuzzyResult {
+        fuzzy::apply_fuzzy(self, patch, options)
+    }
+}
+
+/// The Patch can't be applied to the Document, see `Document::apply`.
+/// `change` is the index of the Change in Patch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ApplyError {
+    /// The Change is not well formed.
+    InvalidChange { change: usize, message: String },
+    /// The Change removes lines beyond the end of Document.
+    OutOfRange {
+        change: usize,
+        /// the end of removed lines, which is the 1-based line number of the last one
+        line_num: usize,
+        len: usize,
+    },
+    /// A removed line doesn't match the line of Document.
+    Mismatch {
+        change: usize,
+        /// line number of the removed line, it's shown 1-based
+        line_num: usize,
+        expected: String,
+        actual: String,
+    },
+}
+
+impl fmt::Display for ApplyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ApplyError::InvalidChange { change, message } => {
+                write!(f, "change {}: {}", change, message)
             }
+            ApplyError::OutOfRange {
+                change,
+                line_num,
+                len,
+            } => write!(
+                f,
+                "change {}: line {} is out of the document of {} lines",
+                change, line_num, len
+            ),
+            ApplyError::Mismatch {
+                change,
+                line_num,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "change {}: line {} is {:?}, expected {:?}",
+                change,
+                line_num + 1,
+                actual,
+                expected
+            ),
         }
+    }
+}
 
-        let last_ele = picked[picked.len() - 1];
-        let end_old = last_ele.0 + last_ele.2;
-        let end_new = last_ele.1 + last_ele.2;
-        if other_lines_count > end_old || this_lines_count > end_new {
-            let mut removed_lines = vec![];
-            for idx in end_old..other_line_num {
-                removed_lines.push(other_vec_ref[idx].clone());
-            }
-            let mut added_lines = vec![];
-            for idx in end_new..this_lines_count {
-                added_lines.push(this_vec_ref[idx].clone());
-            }
-            changes.push((
-                end_old,
-                other_lines_count - end_old,
-                end_new,
-                this_lines_count - end_new,
-                removed_lines,
-                added_lines,
-            ));
+impl std::error::Error for ApplyError {}
+
+impl fmt::Display for Document {
+    /// Convet vec to string, every line ends with `\n`.
+    /// example:
+    /// ```ignore
+    /// let doc = Document::from_fs(s!("tests/base"));
+    /// assert_eq!(s!("A\nB\nC\nD\nE\nF\n"), doc.to_string());
+    /// ```
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for line in self.as_vec_ref() {
+            writeln!(f, "{}", line)?;
         }
-        Patch::from_vec(changes)
+        Ok(())
+    }
+}
+
+impl Sub for Document {
+    type Output = Patch;
+    /// Diff of two document
+    /// algorithm notes:
+    /// Step 1: find the common parts of two documents with Myers' algorithm,
+    /// the common parts form a longest common subsequence.
+    /// The linear space variant is used for large documents, see `DiffOptions`,
+    /// it's `DiffAlgorithm::Minimal` so the result stays minimal however large they are.
+    ///
+    /// Step 2: Construct the changes between the common parts.
+    ///
+    /// Step 3: return Patch.
+    ///
+    /// If you don't kown the edtor matrix,
+    /// You can read paper: http://www.xmailserver.org/diff2.pdf
+    /// The Fig 1. will help you a lot.
+    ///
+    /// @param{self}: the new document
+    /// @param{other}: the old document
+    fn sub(self, other: Self) -> Self::Output {
+        let options = DiffOptions {
+            algorithm: DiffAlgorithm::Minimal,
+            ..DiffOptions::default()
+        };
+        self.diff_with(&other, options)
     }
 }
 
@@ -311,30 +405,30 @@ impl Add<Patch> for Document {
         let patch_vec_ref = patch.as_vec_ref();
         let mut pre_line_num = 0;
 
-        for idx in 0..patch_vec_ref.len() {
+        for change in patch_vec_ref {
             let (
                 old_line_num,
                 removed_line_count,
                 new_line_num,
                 added_line_count,
                 removed_lines,
-                mut added_lines,
-            ) = patch_vec_ref[idx].clone();
+                added_lines,
+            ) = change;
 
-            if old_line_num > pre_line_num {
-                for jdx in pre_line_num..old_line_num {
-                    lines.push(old_vec_ref[jdx].clone());
-                }
+            if *old_line_num > pre_line_num {
+                lines.extend_from_slice(&old_vec_ref[pre_line_num..*old_line_num]);
             }
-            lines.append(&mut added_lines);
+            lines.extend_from_slice(added_lines);
 
             pre_line_num = old_line_num + removed_line_count;
         }
 
-        for idx in pre_line_num..old_vec_ref.len() {
-            lines.push(old_vec_ref[idx].clone());
-        }
-        Self(lines)
+        lines.extend_from_slice(&old_vec_ref[pre_line_num..]);
+        let no_newline_at_eof = patch
+            .no_newline_at_eof()
+            .unwrap_or_else(|| self.no_newline_at_eof());
+        let no_newline_at_eof = no_newline_at_eof && !lines.is_empty();
+        Self(lines, no_newline_at_eof)
     }
 }
 
@@ -388,4 +482,227 @@ mod tests {
         let nnew = base.clone() + patch;
         assert_eq!(new.to_string(), nnew.to_string());
     }
+
+    #[test]
+    // #[ignore]
+    fn test_myers_minimal() {
+        let base = Document::from_fs(s!("tests/base"));
+        let new = Document::from_fs(s!("tests/complex"));
+
+        let patch = new - base;
+        let edit_count: usize = patch.as_vec_ref().iter().map(|c| c.1 + c.3).sum();
+        // remove A, C; add L, X, Y, Z, H, I
+        assert_eq!(8, edit_count);
+    }
+    #[test]
+    // #[ignore]
+    fn test_remove_all() {
+        let base = Document::from_fs(s!("tests/base"));
+        let blank = Document::from_fs(s!("tests/blank"));
+
+        let patch = blank.clone() - base.clone();
+        let nnew = base + patch;
+        assert_eq!(blank, nnew);
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_content() {
+        let base = Document::from_fs(s!("tests/base"));
+        assert!(base.no_newline_at_eof());
+        assert_eq!(s!("A\nB\nC\nD\nE\nF"), base.to_content());
+
+        for content in &["", "\n", "A", "A\n", "A\n\n", "A\r\nB\r\n"] {
+            assert_eq!(*content, Document::from_content(content).to_content());
+        }
+        assert_eq!(
+            vec![s!("A\r"), s!("B\r")],
+            *Document::from_content("A\r\nB\r\n").as_vec_ref()
+        );
+    }
+    #[test]
+    // #[ignore]
+    fn test_newline_at_eof() {
+        let old = Document::from_content("A\nB");
+        let new = Document::from_content("A\nB\n");
+        let patch = new.clone() - old.clone();
+        assert!(patch.as_vec_ref().is_empty());
+        assert_eq!(Some(false), patch.no_newline_at_eof());
+        assert_eq!(new, old + patch);
+    }
+    #[test]
+    // #[ignore]
+    fn test_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ObjectStore::new(dir.path());
+        for file in &["tests/base", "tests/blank", "tests/complex"] {
+            let doc = Document::from_fs(s!(*file));
+            let hash = doc.write_to_store(&store).unwrap();
+            assert_eq!(fs::read(file).unwrap(), store.read(&hash).unwrap());
+            assert_eq!(doc, Document::read_from_store(&store, hash).unwrap());
+        }
+
+        let doc = Document::from_content("A\nB\n");
+        let hash = doc.write_to_store(&store).unwrap();
+        assert_eq!(hash, doc.clone().write_to_store(&store).unwrap());
+        assert_ne!(
+            hash,
+            Document::from_content("A\nB")
+                .write_to_store(&store)
+                .unwrap()
+        );
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_apply() {
+        let base = Document::from_fs(s!("tests/base"));
+        let new = Document::from_fs(s!("tests/complex"));
+        let patch = new.clone() - base.clone();
+        assert_eq!(new, base.apply(&patch).unwrap());
+
+        // applied to the wrong base
+        let other = Document::from_content("A\nB\nZ\nD\nE\nF\n");
+        let patch = Document::from_fs(s!("tests/change_one")) - base.clone();
+        assert_eq!(
+            ApplyError::Mismatch {
+                change: 0,
+                line_num: 2,
+                expected: s!("C"),
+                actual: s!("Z"),
+            },
+            other.apply(&patch).unwrap_err()
+        );
+        assert_eq!(
+            "change 0: line 3 is \"Z\", expected \"C\"",
+            other.apply(&patch).unwrap_err().to_string()
+        );
+        assert_eq!(
+            ApplyError::OutOfRange {
+                change: 0,
+                line_num: 3,
+                len: 2,
+            },
+            Document::from_content("A\nB\n").apply(&patch).unwrap_err()
+        );
+        assert_eq!(
+            "change 0: line 3 is out of the document of 2 lines",
+            Document::from_content("A\nB\n")
+                .apply(&patch)
+                .unwrap_err()
+                .to_string()
+        );
+
+        for changes in [
+            vec![(0, 1, 0, 0, vec![], vec![])],
+            vec![(1, 0, 2, 0, vec![], vec![])],
+            vec![
+                (2, 1, 2, 0, vec![s!("C")], vec![]),
+                (1, 1, 1, 0, vec![s!("B")], vec![]),
+            ],
+        ] {
+            assert!(matches!(
+                base.apply(&Patch::from_vec(changes)),
+                Err(ApplyError::InvalidChange { .. })
+            ));
+        }
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_apply_partial() {
+        let old = Document::from_content("A\nB\nC\nD\nE\nF");
+        let new = Document::from_content("A\nX\nY\nC\nE\nZ\n");
+        let patch = new.clone() - old.clone();
+        assert_eq!(3, patch.as_vec_ref().len());
+
+        let (partial, residual) = old.apply_selected(&patch, &[1]).unwrap();
+        assert_eq!(Document::from_content("A\nB\nC\nE\nF"), partial);
+        assert_eq!(
+            vec![
+                (1, 1, 1, 2, vec![s!("B")], vec![s!("X"), s!("Y")]),
+                (4, 1, 5, 1, vec![s!("F")], vec![s!("Z")]),
+            ],
+            *residual.as_vec_ref()
+        );
+        assert_eq!(Some(false), residual.no_newline_at_eof());
+        assert_eq!(new, partial + residual);
+
+        // accept the additions only
+        let (partial, residual) = old.apply_partial(&patch, |_, change| change.3 > 0).unwrap();
+        assert_eq!(Document::from_content("A\nX\nY\nC\nD\nE\nZ\n"), partial);
+        assert_eq!(new, partial + residual);
+
+        assert!(Document::from_content("A\n")
+            .apply_selected(&patch, &[])
+            .is_err());
+    }
+
+    proptest::proptest! {
+        /// old.apply_partial(patch) + residual == old + patch
+        #[test]
+        fn prop_apply_partial(
+            old in "[a-e\n]{0,80}",
+            new in "[a-e\n]{0,80}",
+            mask: u64,
+        ) {
+            let old = Document::from_content(&old);
+            let new = Document::from_content(&new);
+            let patch = new.clone() - old.clone();
+            let (partial, residual) = old
+                .apply_partial(&patch, |idx, _| mask >> (idx % 64) & 1 == 1)
+                .unwrap();
+            proptest::prop_assert_eq!(Ok(new), partial.apply(&residual));
+        }
+
+        /// old + (new - old) == old.apply(new - old) == new
+        /// A small alphabet makes common lines likely,
+        /// the content may end without `\n`.
+        #[test]
+        fn prop_sub_then_add(
+            old in "[a-e\n]{0,80}",
+            new in "[a-e\n]{0,80}",
+        ) {
+            let old = Document::from_content(&old);
+            let new = Document::from_content(&new);
+            let patch = new.clone() - old.clone();
+            proptest::prop_assert_eq!(Ok(new.clone()), old.apply(&patch));
+            proptest::prop_assert_eq!(new, old + patch);
+        }
+
+        #[test]
+        fn prop_linear_space_diff(
+            old in "[a-e\n]{0,80}",
+            new in "[a-e\n]{0,80}",
+        ) {
+            let old = Document::from_content(&old);
+            let new = Document::from_content(&new);
+            let options = DiffOptions {
+                algorithm: DiffAlgorithm::Minimal,
+                linear_space_threshold: 0,
+            };
+            let patch = new.diff_with(&old, options);
+            let edit_count: usize = patch.as_vec_ref().iter().map(|c| c.1 + c.3).sum();
+            let minimal_count: usize = (new.clone() - old.clone()).as_vec_ref().iter().map(|c| c.1 + c.3).sum();
+            proptest::prop_assert_eq!(minimal_count, edit_count);
+            proptest::prop_assert_eq!(new, old + patch);
+        }
+
+        #[test]
+        fn prop_diff_algorithms(
+            old in "[a-e\n]{0,80}",
+            new in "[a-e\n]{0,80}",
+        ) {
+            let old = Document::from_content(&old);
+            let new = Document::from_content(&new);
+            for algorithm in &[DiffAlgorithm::Myers, DiffAlgorithm::Patience, DiffAlgorithm::Histogram] {
+                let options = DiffOptions {
+                    algorithm: *algorithm,
+                    ..DiffOptions::default()
+                };
+                let patch = new.diff_with(&old, options);
+                proptest::prop_assert_eq!(&new, &(old.clone() + patch));
+            }
+        }
+    }
 }
@@ -0,0 +1,266 @@
+//! This mod implements the fuzzy application of a Patch, like GNU `patch`.
+//! When the base of a Patch has drifted, the line numbers of Changes are not reliable,
+//! so each Change is saved with its context lines in the old document: a Hunk.
+//! A Hunk is located by its removed lines and context lines:
+//! first at the expected line number, then at growing offsets within a window.
+//! If it can't be located, the outer context lines are ignored one by one (the fuzz)
+//! and the search is repeated. A Hunk that still can't be located is rejected,
+//! the other Hunks are applied.
+
+use crate::document::Document;
+use crate::patch::{Change, Patch};
+
+/// A Change and its context lines in the old document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    pub change: Change,
+    /// the lines before the removed lines
+    pub before: Vec<String>,
+    /// the lines after the removed lines
+    pub after: Vec<String>,
+}
+
+/// A Patch whose Changes carry context lines, see Hunk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContextPatch {
+    pub hunks: Vec<Hunk>,
+    /// see Patch
+    pub no_newline_at_eof: Option<bool>,
+}
+
+impl ContextPatch {
+    /// Add up to `context_lines` lines of the old document around each Change,
+    /// `diff -u` uses 3 lines.
+    /// example:
+    /// ```ignore
+    /// let patch = ContextPatch::new(&(new - old.clone()), &old, 3);
+    /// let result = drifted.apply_fuzzy(&patch, FuzzyOptions::default());
+    /// ```
+    pub fn new(patch: &Patch, old: &Document, context_lines: usize) -> Self {
+        let old_vec_ref = old.as_vec_ref();
+        let hunks = patch
+            .as_vec_ref()
+            .iter()
+            .map(|change| {
+                let start = change.0.saturating_sub(context_lines);
+                let end = (change.0 + change.1 + context_lines).min(old_vec_ref.len());
+                Hunk {
+                    change: change.clone(),
+                    before: old_vec_ref[start..change.0].to_vec(),
+                    after: old_vec_ref[change.0 + change.1..end].to_vec(),
+                }
+            })
+            .collect();
+        Self {
+            hunks,
+            no_newline_at_eof: patch.no_newline_at_eof(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FuzzyOptions {
+    /// how far a Hunk is searched from the expected line number
+    pub max_offset: usize,
+    /// how many outer context lines can be ignored on each side
+    pub max_fuzz: usize,
+}
+
+impl Default for FuzzyOptions {
+    /// The default fuzz is the same as GNU `patch`.
+    fn default() -> Self {
+        Self {
+            max_offset: 100,
+            max_fuzz: 2,
+        }
+    }
+}
+
+/// Where a Hunk is applied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Placement {
+    /// line number of the removed lines in the document
+    pub line_num: usize,
+    /// line_num minus the line number in the Change
+    pub offset: isize,
+    /// count of context lines ignored on each side
+    pub fuzz: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FuzzyResult {
+    pub document: Document,
+    /// the Placement of each Hunk, None if the Hunk is rejected
+    pub hunks: Vec<Option<Placement>>,
+}
+
+impl FuzzyResult {
+    /// The indexes of rejected Hunks.
+    pub fn rejected(&self) -> Vec<usize> {
+        (0..self.hunks.len())
+            .filter(|&idx| self.hunks[idx].is_none())
+            .collect()
+    }
+}
+
+/// Apply a ContextPatch to a document whose lines may have drifted from the old document.
+/// The Hunks are located in order and don't overlap each other,
+/// the drift of a located Hunk is expected for the following Hunks.
+pub fn apply_fuzzy(doc: &Document, patch: &ContextPatch, options: FuzzyOptions) -> FuzzyResult {
+    let lines = doc.as_vec_ref();
+    let mut changes = vec![];
+    let mut placements = vec![];
+    // the end of the last located Hunk
+    let mut pre_line_num = 0;
+    let mut drift: isize = 0;
+    // the offset of line numbers made by the applied Changes
+    let mut offset: isize = 0;
+    for hunk in &patch.hunks {
+        let expected = hunk.change.0 as isize + drift;
+        let placement = (0..=options.max_fuzz).find_map(|fuzz| {
+            (0..=options.max_offset as isize)
+                .flat_map(|delta| {
+                    if delta == 0 {
+                        vec![0]
+                    } else {
+                        vec![delta, -delta]
+                    }
+                })
+                .map(|delta| expected + delta)
+                .filter(|&line_num| line_num >= pre_line_num as isize)
+                .find(|&line_num| matches(lines, hunk, line_num as usize, fuzz))
+                .map(|line_num| Placement {
+                    line_num: line_num as usize,
+                    offset: line_num - hunk.change.0 as isize,
+                    fuzz,
+                })
+        });
+        if let Some(placement) = placement {
+            let mut change = hunk.change.clone();
+            change.0 = placement.line_num;
+            change.2 = (placement.line_num as isize + offset) as usize;
+            offset += change.3 as isize - change.1 as isize;
+            pre_line_num = change.0 + change.1;
+            drift = placement.offset;
+            changes.push(change);
+        }
+        placements.push(placement);
+    }
+    let patch = Patch::from_vec(changes).with_no_newline_at_eof(patch.no_newline_at_eof);
+    FuzzyResult {
+        document: doc.clone() + patch,
+        hunks: placements,
+    }
+}
+
+/// Check whether the Hunk matches the lines at line_num,
+/// ignoring `fuzz` outer context lines on each side.
+fn matches(lines: &[String], hunk: &Hunk, line_num: usize, fuzz: usize) -> bool {
+    let removed = &hunk.change.4;
+    let before = &hunk.before[fuzz.min(hunk.before.len())..];
+    let after = &hunk.after[..hunk.after.len() - fuzz.min(hunk.after.len())];
+    line_num >= before.len()
+        && line_num + removed.len() + after.len() <= lines.len()
+        && lines[line_num - before.len()..line_num] == before[..]
+        && lines[line_num..line_num + removed.len()] == removed[..]
+        && lines[line_num + removed.len()..line_num + removed.len() + after.len()] == after[..]
+}
+
+#[cfg(test)]
+mod tests {
+    use self::super::*;
+    use crate::macros::s;
+
+    fn doc(content: &str) -> Document {
+        Document::from_content(content)
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_apply_offset() {
+        let old = doc("A\nB\nC\nD\nE\nF\nG\nH\n");
+        let new = doc("A\nB\nX\nD\nE\nF\nG\nY\n");
+        let patch = ContextPatch::new(&(new - old.clone()), &old, 2);
+        assert_eq!(vec![s!("A"), s!("B")], patch.hunks[0].before);
+        assert_eq!(vec![s!("D"), s!("E")], patch.hunks[0].after);
+        assert!(patch.hunks[1].after.is_empty());
+
+        // two lines are inserted at the start
+        let drifted = doc("0\n1\nA\nB\nC\nD\nE\nF\nG\nH\n");
+        let result = apply_fuzzy(&drifted, &patch, FuzzyOptions::default());
+        assert_eq!(doc("0\n1\nA\nB\nX\nD\nE\nF\nG\nY\n"), result.document);
+        assert_eq!(
+            vec![
+                Some(Placement {
+                    line_num: 4,
+                    offset: 2,
+                    fuzz: 0
+                }),
+                Some(Placement {
+                    line_num: 9,
+                    offset: 2,
+                    fuzz: 0
+                }),
+            ],
+            result.hunks
+        );
+
+        // out of the window
+        let options = FuzzyOptions {
+            max_offset: 1,
+            ..FuzzyOptions::default()
+        };
+        assert_eq!(
+            vec![0, 1],
+            apply_fuzzy(&drifted, &patch, options).rejected()
+        );
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_apply_fuzz() {
+        let old = doc("A\nB\nC\nD\nE\nF\nG\nH\nI\n");
+        let new = doc("A\nB\nC\nX\nE\nF\nG\nY\nI\n");
+        let patch = ContextPatch::new(&(new - old.clone()), &old, 3);
+
+        // A is changed: the first Hunk is located with fuzz 1,
+        // H is removed: the second Hunk is rejected
+        let drifted = doc("a\nB\nC\nD\nE\nF\nG\nI\n");
+        let result = apply_fuzzy(&drifted, &patch, FuzzyOptions::default());
+        assert_eq!(doc("a\nB\nC\nX\nE\nF\nG\nI\n"), result.document);
+        assert_eq!(
+            Some(Placement {
+                line_num: 3,
+                offset: 0,
+                fuzz: 1
+            }),
+            result.hunks[0]
+        );
+        assert_eq!(vec![1], result.rejected());
+
+        let options = FuzzyOptions {
+            max_fuzz: 0,
+            ..FuzzyOptions::default()
+        };
+        assert_eq!(
+            vec![0, 1],
+            apply_fuzzy(&drifted, &patch, options).rejected()
+        );
+    }
+
+    proptest::proptest! {
+        /// a ContextPatch applies to its own old document exactly
+        #[test]
+        fn prop_apply_exact(
+            old in "[a-e\n]{0,80}",
+            new in "[a-e\n]{0,80}",
+        ) {
+            let old = Document::from_content(&old);
+            let new = Document::from_content(&new);
+            let patch = ContextPatch::new(&(new.clone() - old.clone()), &old, 3);
+            let result = apply_fuzzy(&old, &patch, FuzzyOptions::default());
+            proptest::prop_assert_eq!(new, result.document);
+            proptest::prop_assert!(result.hunks.iter().all(|h| h.is_some_and(|p| p.offset == 0)));
+        }
+    }
+}
@@ -0,0 +1,203 @@
+//! This mod implements the intra-line highlighting of Changes.
+//! A Change replacing a line with a slightly edited line shows the whole line as changed,
+//! so the removed and added lines are paired in order, and each pair is diffed again
+//! on words or chars, the result is a list of Spans on each line.
+//! Chinese prose has no spaces between words, so each CJK char is a word.
+
+use crate::diff::{self, DiffOptions};
+use crate::macros::s;
+use crate::patch::Change;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Granularity {
+    /// runs of letters and digits, runs of whitespace, and single other chars
+    Word,
+    Char,
+}
+
+/// A part of a line, `[start, end)` are byte offsets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    /// false if the part is common to the paired line
+    pub changed: bool,
+}
+
+/// The Spans of each removed line and each added line of a Change.
+/// The i-th removed line is paired with the i-th added line,
+/// a line without pair is a single changed Span, an empty line has no Span.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangeSpans {
+    pub removed: Vec<Vec<Span>>,
+    pub added: Vec<Vec<Span>>,
+}
+
+/// Highlight the lines of a Change.
+pub fn change_spans(change: &Change, granularity: Granularity) -> ChangeSpans {
+    let mut removed = vec![];
+    let mut added = vec![];
+    for i in 0..change.1.max(change.3) {
+        match (change.4.get(i), change.5.get(i)) {
+            (Some(old), Some(new)) => {
+                let (old_spans, new_spans) = line_spans(old, new, granularity);
+                removed.push(old_spans);
+                added.push(new_spans);
+            }
+            (Some(old), None) => removed.push(whole(old)),
+            (None, Some(new)) => added.push(whole(new)),
+            (None, None) => unreachable!(),
+        }
+    }
+    ChangeSpans { removed, added }
+}
+
+/// Diff the tokens of old and new lines, return the Spans of both lines.
+pub fn line_spans(old: &str, new: &str, granularity: Granularity) -> (Vec<Span>, Vec<Span>) {
+    let old_tokens = tokenize(old, granularity);
+    let new_tokens = tokenize(new, granularity);
+    let old_texts: Vec<String> = old_tokens.iter().map(|&(s, e)| s!(&old[s..e])).collect();
+    let new_texts: Vec<String> = new_tokens.iter().map(|&(s, e)| s!(&new[s..e])).collect();
+    let commons = diff::diff(&old_texts, &new_texts, DiffOptions::default());
+
+    let mut old_common = vec![false; old_tokens.len()];
+    let mut new_common = vec![false; new_tokens.len()];
+    for &(old_start, new_start, len) in &commons {
+        old_common[old_start..old_start + len].fill(true);
+        new_common[new_start..new_start + len].fill(true);
+    }
+    (
+        spans(&old_tokens, &old_common),
+        spans(&new_tokens, &new_common),
+    )
+}
+
+/// Split a line into tokens, return the byte ranges.
+fn tokenize(line: &str, granularity: Granularity) -> Vec<(usize, usize)> {
+    // the class of char: 0 letter or digit, 1 whitespace, 2 the others which are single tokens
+    let class = |c: char| {
+        if granularity == Granularity::Char || is_cjk(c) {
+            2
+        } else if c.is_alphanumeric() || c == '_' {
+            0
+        } else if c.is_whitespace() {
+            1
+        } else {
+            2
+        }
+    };
+    let mut tokens: Vec<(usize, usize)> = vec![];
+    let mut pre_class = 2;
+    for (idx, c) in line.char_indices() {
+        let end = idx + c.len_utf8();
+        match tokens.last_mut() {
+            Some(token) if class(c) == pre_class && pre_class != 2 => token.1 = end,
+            _ => tokens.push((idx, end)),
+        }
+        pre_class = class(c);
+    }
+    tokens
+}
+
+/// Chinese, Japanese and Korean chars.
+fn is_cjk(c: char) -> bool {
+    matches!(c as u32,
+        0x2E80..=0x303E | 0x3041..=0x33FF | 0x3400..=0x4DBF | 0x4E00..=0x9FFF
+        | 0xAC00..=0xD7A3 | 0xF900..=0xFAFF | 0xFF00..=0xFFEF | 0x20000..=0x3FFFD)
+}
+
+/// Merge the adjacent tokens of the same state into Spans.
+fn spans(tokens: &[(usize, usize)], common: &[bool]) -> Vec<Span> {
+    let mut spans: Vec<Span> = vec![];
+    for (&(start, end), &common) in tokens.iter().zip(common) {
+        match spans.last_mut() {
+            Some(span) if span.changed != common => span.end = end,
+            _ => spans.push(Span {
+                start,
+                end,
+                changed: !common,
+            }),
+        }
+    }
+    spans
+}
+
+/// A line without pair is changed as a whole.
+fn whole(line: &str) -> Vec<Span> {
+    if line.is_empty() {
+        return vec![];
+    }
+    vec![Span {
+        start: 0,
+        end: line.len(),
+        changed: true,
+    }]
+}
+
+#[cfg(test)]
+mod tests {
+    use self::super::*;
+    use crate::document::Document;
+
+    /// The changed parts of a line.
+    fn changed<'a>(line: &'a str, spans: &[Span]) -> Vec<&'a str> {
+        spans
+            .iter()
+            .filter(|span| span.changed)
+            .map(|span| &line[span.start..span.end])
+            .collect()
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_tokenize() {
+        let line = "Hello, wrold  2022!文档";
+        let tokens: Vec<&str> = tokenize(line, Granularity::Word)
+            .iter()
+            .map(|&(s, e)| &line[s..e])
+            .collect();
+        assert_eq!(
+            vec!["Hello", ",", " ", "wrold", "  ", "2022", "!", "文", "档"],
+            tokens
+        );
+        assert_eq!(5, tokenize("a b文档", Granularity::Char).len());
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_line_spans() {
+        let old = "The quick brwon fox jumps";
+        let new = "The quick brown fox jumped";
+        let (old_spans, new_spans) = line_spans(old, new, Granularity::Word);
+        assert_eq!(vec!["brwon", "jumps"], changed(old, &old_spans));
+        assert_eq!(vec!["brown", "jumped"], changed(new, &new_spans));
+        // the spans cover the whole line
+        assert_eq!(0, old_spans[0].start);
+        assert_eq!(old.len(), old_spans.last().unwrap().end);
+        assert!(old_spans.windows(2).all(|w| w[0].end == w[1].start));
+
+        let (old_spans, new_spans) = line_spans(old, new, Granularity::Char);
+        assert_eq!(vec!["w", "s"], changed(old, &old_spans));
+        assert_eq!(vec!["w", "ed"], changed(new, &new_spans));
+
+        let (old_spans, new_spans) = line_spans("修改文档", "修订文档", Granularity::Word);
+        assert_eq!(vec!["改"], changed("修改文档", &old_spans));
+        assert_eq!(vec!["订"], changed("修订文档", &new_spans));
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_change_spans() {
+        let old = Document::from_content("A\nHello wrold\nC\n");
+        let new = Document::from_content("A\nHello world\nNew line\n\nC\n");
+        let patch = new - old;
+        let spans = patch.spans(Granularity::Word);
+        assert_eq!(1, spans.len());
+        assert_eq!(1, spans[0].removed.len());
+        assert_eq!(3, spans[0].added.len());
+        assert_eq!(vec!["wrold"], changed("Hello wrold", &spans[0].removed[0]));
+        assert_eq!(vec!["world"], changed("Hello world", &spans[0].added[0]));
+        assert_eq!(vec!["New line"], changed("New line", &spans[0].added[1]));
+        assert!(spans[0].added[2].is_empty());
+    }
+}
@@ -0,0 +1,678 @@
+//! This mod manages the history of a document.
+//! The history is a chain of Commits saved in the ObjectStore,
+//! the branch heads and releases are saved in the RefStore.
+//!
+//! To get the Document at a Commit, we walk the parents back to the nearest release,
+//! then apply the following Patches to the released Document:
+//! release_doc + p1 + p2 + ... = doc
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::io;
+use std::sync::{Arc, Mutex};
+
+use crate::commit::Commit;
+use crate::document::Document;
+use crate::macros::s;
+use crate::merge::{self, MarkedConflict, MarkerError};
+use crate::patch::{Conflict, Patch};
+use crate::refs::{RefError, RefStore};
+use crate::store::ObjectStore;
+use crate::version::Version;
+
+/// The prefix of release refs, each release ref points to a Version.
+pub const RELEASES: &str = "releases";
+
+#[derive(Debug)]
+pub enum HistoryError {
+    Io(io::Error),
+    Ref(RefError),
+    /// The file name is not valid, see Book.
+    InvalidFile(String),
+    /// The Document still has a conflict rendered with markers, see `merge::find_markers`.
+    UnresolvedConflict(Box<MarkedConflict>),
+    /// A conflict marker of the Document is out of place, see `merge::find_markers`.
+    InvalidMarker(MarkerError),
+    /// The reverted Commit conflicts with the following Commits, see `History::revert`.
+    Conflict(Box<Conflict>),
+}
+
+impl fmt::Display for HistoryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HistoryError::Io(e) => write!(f, "{}", e),
+            HistoryError::Ref(e) => write!(f, "{}", e),
+            HistoryError::InvalidFile(file) => write!(f, "invalid file name: {:?}", file),
+            HistoryError::UnresolvedConflict(conflict) => write!(
+                f,
+                "unresolved conflict at lines {}-{}",
+                conflict.start + 1,
+                conflict.end + 1
+            ),
+            HistoryError::InvalidMarker(e) => write!(f, "invalid conflict marker at {}", e),
+            HistoryError::Conflict(conflict) => {
+                write!(f, "conflict at line {}", conflict.theirs.0 + 1)
+            }
+        }
+    }
+}
+
+impl std::error::Error for HistoryError {}
+
+impl From<io::Error> for HistoryError {
+    fn from(e: io::Error) -> Self {
+        HistoryError::Io(e)
+    }
+}
+
+impl From<RefError> for HistoryError {
+    fn from(e: RefError) -> Self {
+        HistoryError::Ref(e)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, HistoryError>;
+
+/// The policy to write snapshots automatically on commit.
+/// A snapshot saves the full Document in the Commit,
+/// so the checkout replays at most a bounded chain of Patches since the last snapshot or release.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SnapshotPolicy {
+    /// Write a snapshot when the chain has this many Patches.
+    pub max_chain_length: Option<usize>,
+    /// Write a snapshot when the total size of Patches in the chain
+    /// exceeds this fraction of the Document size.
+    pub max_patch_ratio: Option<f64>,
+}
+
+impl SnapshotPolicy {
+    /// Never write snapshots, the chain is only cut by releases.
+    pub fn never() -> Self {
+        Self {
+            max_chain_length: None,
+            max_patch_ratio: None,
+        }
+    }
+
+    /// Check whether a snapshot should be written,
+    /// the chain includes the Patch of the new Commit.
+    /// A chain of one Patch never needs a snapshot.
+    pub fn should_snapshot(&self, chain_length: usize, patch_size: u64, doc_size: u64) -> bool {
+        chain_length > 1
+            && (self.max_chain_length.is_some_and(|max| chain_length >= max)
+                || self
+                    .max_patch_ratio
+                    .is_some_and(|ratio| patch_size as f64 > ratio * doc_size as f64))
+    }
+}
+
+impl Default for SnapshotPolicy {
+    fn default() -> Self {
+        Self {
+            max_chain_length: Some(100),
+            max_patch_ratio: Some(2.0),
+        }
+    }
+}
+
+/// The default capacity of RevisionCache.
+pub const CACHE_CAPACITY: usize = 64;
+
+/// The cache of recently checked out Documents by Commit hash,
+/// the least recently used one is dropped when the cache is full.
+/// A Commit hash covers the whole parent chain, so it always maps to the same Document,
+/// the cache can be shared by the histories of different documents.
+#[derive(Debug)]
+pub struct RevisionCache {
+    capacity: usize,
+    docs: HashMap<String, Document>,
+    /// commit hashes, the least recently used first
+    order: VecDeque<String>,
+}
+
+impl RevisionCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            docs: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.docs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.docs.is_empty()
+    }
+
+    pub fn contains(&self, commit: &str) -> bool {
+        self.docs.contains_key(commit)
+    }
+
+    pub fn get(&mut self, commit: &str) -> Option<Document> {
+        let doc = self.docs.get(commit)?.clone();
+        self.touch(commit);
+        Some(doc)
+    }
+
+    pub fn put(&mut self, commit: &str, doc: Document) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.docs.insert(s!(commit), doc).is_some() {
+            self.touch(commit);
+            return;
+        }
+        self.order.push_back(s!(commit));
+        if self.order.len() > self.capacity {
+            let oldest = self.order.pop_front().unwrap();
+            self.docs.remove(&oldest);
+        }
+    }
+
+    /// Mark a commit as the most recently used.
+    fn touch(&mut self, commit: &str) {
+        if let Some(idx) = self.order.iter().position(|c| c == commit) {
+            let commit = self.order.remove(idx).unwrap();
+            self.order.push_back(commit);
+        }
+    }
+}
+
+/// The history of a document.
+/// example:
+/// ```ignore
+/// let history = History::new(ObjectStore::new("objects"), RefStore::new("refs/chapter1", s!("eq99")));
+/// let c1 = history.commit(MAIN, &v1, "eq99", "create")?;
+/// history.release(&c1, "v1.0", "first release", "eq99")?;
+/// let c2 = history.commit(MAIN, &v2, "eq99", "fix typo")?;
+/// assert_eq!(v2, history.checkout(&c2)?); // v1 is read from store, then + p2
+/// ```
+#[derive(Debug, Clone)]
+pub struct History {
+    store: ObjectStore,
+    refs: RefStore,
+    cache: Arc<Mutex<RevisionCache>>,
+    policy: SnapshotPolicy,
+}
+
+impl History {
+    pub fn new(store: ObjectStore, refs: RefStore) -> Self {
+        let cache = Arc::new(Mutex::new(RevisionCache::new(CACHE_CAPACITY)));
+        Self {
+            store,
+            refs,
+            cache,
+            policy: SnapshotPolicy::default(),
+        }
+    }
+
+    pub fn with_snapshot_policy(self, policy: SnapshotPolicy) -> Self {
+        Self { policy, ..self }
+    }
+
+    /// Use a shared cache, see Book.
+    pub fn with_cache(self, cache: Arc<Mutex<RevisionCache>>) -> Self {
+        Self { cache, ..self }
+    }
+
+    pub fn cache(&self) -> &Arc<Mutex<RevisionCache>> {
+        &self.cache
+    }
+
+    pub fn store(&self) -> &ObjectStore {
+        &self.store
+    }
+
+    pub fn refs(&self) -> &RefStore {
+        &self.refs
+    }
+
+    /// Commit a new Document on a branch, return the hash of Commit.
+    /// The Document with unresolved conflict markers is refused.
+    /// The Patch is calculated against the Document at the head of branch,
+    /// a snapshot of the Document is written if the SnapshotPolicy says so,
+    /// then the head is moved by compare-and-swap,
+    /// so a concurrent commit on the same branch fails instead of being lost.
+    pub fn commit(
+        &self,
+        branch: &str,
+        doc: &Document,
+        author: &str,
+        message: &str,
+    ) -> Result<String> {
+        let conflicts = merge::find_markers(doc).map_err(HistoryError::InvalidMarker)?;
+        if let Some(conflict) = conflicts.into_iter().next() {
+            return Err(HistoryError::UnresolvedConflict(Box::new(conflict)));
+        }
+        let parent = self.refs.read(branch)?;
+        self.commit_on(branch, parent, doc, author, message)
+    }
+
+    /// Commit a new Document on the parent, the branch is updated only if it's still at parent.
+    fn commit_on(
+        &self,
+        branch: &str,
+        parent: Option<String>,
+        doc: &Document,
+        author: &str,
+        message: &str,
+    ) -> Result<String> {
+        let snapshots = self.release_snapshots()?;
+        let old = match &parent {
+            Some(hash) => self.checkout_with(hash, &snapshots)?,
+            None => Document::from_vec(vec![]),
+        };
+        let patch_hash = (doc.clone() - old).write_to_store(&self.store)?;
+
+        let (chain_length, patch_size) = self.chain_with(parent.as_deref(), &snapshots)?;
+        let chain_length = chain_length + 1;
+        let patch_size = patch_size + self.store.size(&patch_hash)?;
+        let doc_size = doc.to_content().len() as u64;
+        let mut commit = Commit::new(patch_hash, parent.clone(), s!(author), s!(message));
+        if self
+            .policy
+            .should_snapshot(chain_length, patch_size, doc_size)
+        {
+            commit.snapshot = Some(doc.write_to_store(&self.store)?);
+        }
+        let hash = commit.write_to_store(&self.store)?;
+        self.refs.update(branch, parent.as_deref(), &hash, author)?;
+        Ok(hash)
+    }
+
+    /// Revert a Commit on a branch by committing its inverted Patch, return the hash of Commit.
+    /// If the branch has moved on, the inverted Patch is rebased onto the following Commits,
+    /// it fails with `HistoryError::Conflict` if they edit the same lines.
+    pub fn revert(&self, branch: &str, commit: &str, author: &str) -> Result<String> {
+        let reverted = Commit::read_from_store(&self.store, s!(commit))?;
+        let patch = Patch::read_from_store(&self.store, reverted.patch.clone())?;
+        let parent = self.refs.read(branch)?;
+        let head = match &parent {
+            Some(hash) => self.checkout(hash)?,
+            None => Document::from_vec(vec![]),
+        };
+        let upstream = head.clone() - self.checkout(commit)?;
+        let inverted = patch
+            .invert()
+            .rebase_onto(&upstream)
+            .map_err(|conflict| HistoryError::Conflict(Box::new(conflict)))?;
+        let subject = reverted.message.lines().next().unwrap_or_default();
+        let message = format!("Revert {:?}\n\nThis reverts commit {}.", subject, commit);
+        self.commit_on(branch, parent, &(head + inverted), author, &message)
+    }
+
+    /// Mark a Commit as a release, the full Document is saved in the store.
+    /// Return the hash of Version.
+    pub fn release(&self, commit: &str, name: &str, notes: &str, user: &str) -> Result<String> {
+        let doc = self.checkout(commit)?;
+        let doc_hash = doc.write_to_store(&self.store)?;
+        let version = Version::new(s!(commit), doc_hash, s!(notes));
+        let hash = version.write_to_store(&self.store)?;
+        self.refs
+            .update(&format!("{}/{}", RELEASES, name), None, &hash, user)?;
+        Ok(hash)
+    }
+
+    /// The length and total Patch size of the chain from a Commit
+    /// back to the last snapshot or release.
+    pub fn chain_since_snapshot(&self, commit: Option<&str>) -> Result<(usize, u64)> {
+        self.chain_with(commit, &self.release_snapshots()?)
+    }
+
+    /// See `chain_since_snapshot`, snapshots are the released Commits and their Documents.
+    fn chain_with(
+        &self,
+        commit: Option<&str>,
+        snapshots: &HashMap<String, String>,
+    ) -> Result<(usize, u64)> {
+        let mut chain_length = 0;
+        let mut patch_size = 0;
+        let mut cursor = commit.map(String::from);
+        while let Some(hash) = cursor {
+            if snapshots.contains_key(&hash) {
+                break;
+            }
+            let commit = Commit::read_from_store(&self.store, hash)?;
+            if commit.snapshot.is_some() {
+                break;
+            }
+            chain_length += 1;
+            patch_size += self.store.size(&commit.patch)?;
+            cursor = commit.parent;
+        }
+        Ok((chain_length, patch_size))
+    }
+
+    /// Map the released Commits to their Documents,
+    /// only the refs under `releases/` are read.
+    fn release_snapshots(&self) -> Result<HashMap<String, String>> {
+        Ok(self
+            .releases()?
+            .into_iter()
+            .map(|(_, version)| (version.commit, version.document))
+            .collect())
+    }
+
+    /// List the releases by name.
+    pub fn releases(&self) -> Result<Vec<(String, Version)>> {
+        let mut releases = vec![];
+        for (name, hash) in self.refs.list_prefix(RELEASES)? {
+            if let Some(name) = name
+                .strip_prefix(RELEASES)
+                .and_then(|name| name.strip_prefix('/'))
+            {
+                releases.push((s!(name), Version::read_from_store(&self.store, hash)?));
+            }
+        }
+        Ok(releases)
+    }
+
+    /// Reconstruct the Document at a Commit.
+    /// Step 1: walk the parents back until a cached Commit, a released Commit,
+    /// a Commit with snapshot or the first Commit.
+    /// Step 2: take the cached, released or snapshot Document, or start from an empty Document.
+    /// Step 3: apply the Patches after it.
+    /// The result is put into the cache, so browsing the history commit by commit
+    /// only applies one Patch each time.
+    pub fn checkout(&self, commit: &str) -> Result<Document> {
+        if let Some(doc) = self.cache.lock().unwrap().get(commit) {
+            return Ok(doc);
+        }
+        self.checkout_with(commit, &self.release_snapshots()?)
+    }
+
+    /// See `checkout`, snapshots are the released Commits and their Documents.
+    fn checkout_with(&self, commit: &str, snapshots: &HashMap<String, String>) -> Result<Document> {
+        let mut patches = vec![];
+        let mut cursor = s!(commit);
+        let base = loop {
+            if let Some(doc) = self.cache.lock().unwrap().get(&cursor) {
+                break doc;
+            }
+            if let Some(doc_hash) = snapshots.get(&cursor) {
+                break Document::read_from_store(&self.store, doc_hash.clone())?;
+            }
+            let commit = Commit::read_from_store(&self.store, cursor)?;
+            if let Some(doc_hash) = commit.snapshot {
+                break Document::read_from_store(&self.store, doc_hash)?;
+            }
+            patches.push(commit.patch);
+            match commit.parent {
+                Some(parent) => cursor = parent,
+                None => break Document::from_vec(vec![]),
+            }
+        };
+
+        let mut doc = base;
+        for patch_hash in patches.into_iter().rev() {
+            doc = doc + Patch::read_from_store(&self.store, patch_hash)?;
+        }
+        self.cache.lock().unwrap().put(commit, doc.clone());
+        Ok(doc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use self::super::*;
+    use crate::refs::MAIN;
+
+    fn history(dir: &tempfile::TempDir) -> History {
+        History::new(
+            ObjectStore::new(dir.path().join("objects")),
+            RefStore::new(dir.path().join("refs"), s!("eq99")),
+        )
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_commit_and_checkout() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = history(&dir);
+        let base = Document::from_fs(s!("tests/base"));
+        let new = Document::from_fs(s!("tests/complex"));
+
+        let c1 = history.commit(MAIN, &base, "eq99", "create").unwrap();
+        let c2 = history.commit(MAIN, &new, "eq99", "edit").unwrap();
+        assert_eq!(Some(c2.clone()), history.refs().read(MAIN).unwrap());
+        assert_eq!(base, history.checkout(&c1).unwrap());
+        assert_eq!(new, history.checkout(&c2).unwrap());
+
+        assert!(matches!(
+            history.commit(MAIN, &base, "alice", "revert"),
+            Err(HistoryError::Ref(RefError::PermissionDenied { .. }))
+        ));
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_release() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = history(&dir);
+        let base = Document::from_fs(s!("tests/base"));
+        let one = Document::from_fs(s!("tests/change_one"));
+        let all = Document::from_fs(s!("tests/change_all"));
+
+        let c1 = history.commit(MAIN, &base, "eq99", "create").unwrap();
+        let c2 = history.commit(MAIN, &one, "eq99", "edit").unwrap();
+        history
+            .release(&c2, "v1.0", "first release", "eq99")
+            .unwrap();
+        let c3 = history.commit(MAIN, &all, "eq99", "rewrite").unwrap();
+
+        let releases = history.releases().unwrap();
+        assert_eq!(1, releases.len());
+        assert_eq!(s!("v1.0"), releases[0].0);
+        assert_eq!(c2, releases[0].1.commit);
+
+        // The patches before the release are not needed any more.
+        let c1_commit = Commit::read_from_store(history.store(), c1.clone()).unwrap();
+        let c1_patch = dir
+            .path()
+            .join("objects")
+            .join(&c1_commit.patch[..2])
+            .join(&c1_commit.patch[2..]);
+        std::fs::remove_file(c1_patch).unwrap();
+        // drop the cached documents, so they are reconstructed from the release.
+        *history.cache().lock().unwrap() = RevisionCache::new(CACHE_CAPACITY);
+        assert_eq!(one, history.checkout(&c2).unwrap());
+        assert_eq!(all, history.checkout(&c3).unwrap());
+        assert!(history.checkout(&c1).is_err());
+
+        // only the maintainer makes releases
+        assert!(history.release(&c3, "v2.0", "", "alice").is_err());
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_revision_cache() {
+        let mut cache = RevisionCache::new(2);
+        let base = Document::from_fs(s!("tests/base"));
+        let blank = Document::from_fs(s!("tests/blank"));
+        cache.put("c1", base.clone());
+        cache.put("c2", blank.clone());
+        assert_eq!(Some(base.clone()), cache.get("c1"));
+        // c2 is the least recently used
+        cache.put("c3", blank.clone());
+        assert!(cache.contains("c1"));
+        assert!(!cache.contains("c2"));
+        assert!(cache.contains("c3"));
+        assert_eq!(2, cache.len());
+
+        let mut cache = RevisionCache::new(0);
+        cache.put("c1", base);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_checkout_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = history(&dir);
+        let base = Document::from_fs(s!("tests/base"));
+        let new = Document::from_fs(s!("tests/complex"));
+        let c1 = history.commit(MAIN, &base, "eq99", "create").unwrap();
+        let c2 = history.commit(MAIN, &new, "eq99", "edit").unwrap();
+        history.cache().lock().unwrap().put(&c1, base.clone());
+
+        // only the patch of c2 is read, then c2 is cached
+        let c1_commit = Commit::read_from_store(history.store(), c1.clone()).unwrap();
+        let c1_patch = dir
+            .path()
+            .join("objects")
+            .join(&c1_commit.patch[..2])
+            .join(&c1_commit.patch[2..]);
+        std::fs::remove_file(c1_patch).unwrap();
+        assert_eq!(new, history.checkout(&c2).unwrap());
+        assert!(history.cache().lock().unwrap().contains(&c2));
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_snapshot_policy() {
+        let policy = SnapshotPolicy {
+            max_chain_length: Some(3),
+            max_patch_ratio: Some(0.5),
+        };
+        assert!(!policy.should_snapshot(1, 100, 10));
+        assert!(!policy.should_snapshot(2, 5, 10));
+        assert!(policy.should_snapshot(3, 5, 10));
+        assert!(policy.should_snapshot(2, 6, 10));
+        assert!(!SnapshotPolicy::never().should_snapshot(1000, 1000, 1));
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_snapshot_on_commit() {
+        let dir = tempfile::tempdir().unwrap();
+        let policy = SnapshotPolicy {
+            max_chain_length: Some(3),
+            max_patch_ratio: None,
+        };
+        let history = history(&dir).with_snapshot_policy(policy);
+        let docs: Vec<_> = (0..7)
+            .map(|i| Document::from_vec((0..=i).map(|j| format!("line {}", j)).collect()))
+            .collect();
+        let mut commits = vec![];
+        for doc in &docs {
+            commits.push(history.commit(MAIN, doc, "eq99", "edit").unwrap());
+        }
+
+        // every 3rd commit has a snapshot
+        let snapshots: Vec<_> = commits
+            .iter()
+            .map(|c| {
+                Commit::read_from_store(history.store(), c.clone())
+                    .unwrap()
+                    .snapshot
+                    .is_some()
+            })
+            .collect();
+        assert_eq!(
+            vec![false, false, true, false, false, true, false],
+            snapshots
+        );
+        assert_eq!(
+            (
+                1,
+                history
+                    .store()
+                    .size(
+                        &Commit::read_from_store(history.store(), commits[6].clone())
+                            .unwrap()
+                            .patch
+                    )
+                    .unwrap()
+            ),
+            history.chain_since_snapshot(Some(&commits[6])).unwrap()
+        );
+
+        // The patches before the snapshot are not needed.
+        for commit in &commits[..6] {
+            let patch = Commit::read_from_store(history.store(), commit.clone())
+                .unwrap()
+                .patch;
+            let path = dir
+                .path()
+                .join("objects")
+                .join(&patch[..2])
+                .join(&patch[2..]);
+            std::fs::remove_file(path).unwrap();
+        }
+        *history.cache().lock().unwrap() = RevisionCache::new(CACHE_CAPACITY);
+        assert_eq!(docs[5], history.checkout(&commits[5]).unwrap());
+        assert_eq!(docs[6], history.checkout(&commits[6]).unwrap());
+        assert!(history.checkout(&commits[4]).is_err());
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_commit_unresolved() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = history(&dir);
+        let base = Document::from_content("A\nB\nC\n");
+        let ours = Document::from_content("A\nX\nC\n");
+        let theirs = Document::from_content("A\nY\nC\n");
+        let merged = Document::merge3(&base, &ours, &theirs).render(
+            merge::MarkerStyle::Merge,
+            "main",
+            "alice",
+        );
+        match history.commit(MAIN, &merged, "eq99", "merge") {
+            Err(e @ HistoryError::UnresolvedConflict(_)) => {
+                assert_eq!("unresolved conflict at lines 2-6", e.to_string())
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        let invalid = Document::from_content("A\n>>>>>>> alice\n");
+        match history.commit(MAIN, &invalid, "eq99", "merge") {
+            Err(e @ HistoryError::InvalidMarker(_)) => assert_eq!(
+                "invalid conflict marker at line 2: marker outside of conflict",
+                e.to_string()
+            ),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert_eq!(None, history.refs().read(MAIN).unwrap());
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_revert() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = history(&dir);
+        let v1 = Document::from_content("A\nB\nC\nD\nE\n");
+        let v2 = Document::from_content("A\nX\nC\nD\nE\n");
+        let v3 = Document::from_content("A\nX\nC\nD\nE\nF\n");
+        history.commit(MAIN, &v1, "eq99", "create").unwrap();
+        let c2 = history.commit(MAIN, &v2, "eq99", "accept X").unwrap();
+        let c3 = history.commit(MAIN, &v3, "eq99", "append F").unwrap();
+
+        let c4 = history.revert(MAIN, &c2, "eq99").unwrap();
+        assert_eq!(
+            Document::from_content("A\nB\nC\nD\nE\nF\n"),
+            history.checkout(&c4).unwrap()
+        );
+        let commit = Commit::read_from_store(history.store(), c4.clone()).unwrap();
+        assert_eq!(Some(c3), commit.parent);
+        assert_eq!(
+            format!("Revert \"accept X\"\n\nThis reverts commit {}.", c2),
+            commit.message
+        );
+
+        // revert the revert
+        let c5 = history.revert(MAIN, &c4, "eq99").unwrap();
+        assert_eq!(v3, history.checkout(&c5).unwrap());
+
+        // X is changed again after c2
+        let v6 = Document::from_content("A\nY\nC\nD\nE\nF\n");
+        let c6 = history.commit(MAIN, &v6, "eq99", "change X").unwrap();
+        assert!(matches!(
+            history.revert(MAIN, &c2, "eq99"),
+            Err(HistoryError::Conflict(_))
+        ));
+        assert_eq!(Some(c6), history.refs().read(MAIN).unwrap());
+    }
+}
@@ -0,0 +1,344 @@
+//! This mod renders a Patch against its base Document as an HTML table,
+//! for the admin of the learning website to review suggestions.
+//! The table is made of the hunks of `render::hunks`, it's styled by the CSS classes:
+//! ```text
+//! carbon-diff      the table, with carbon-side-by-side or carbon-unified
+//! carbon-hunk      the row of a hunk header
+//! carbon-context   the row or cell of a common line
+//! carbon-removed   the row or cell of a removed line
+//! carbon-added     the row or cell of an added line
+//! carbon-changed   the row of a removed line paired with an added line
+//! carbon-empty     the cell of the missing side in side-by-side view
+//! carbon-line-num  the cell of a line number
+//! carbon-line      the cell of a line
+//! carbon-highlight the span of changed words in a line, see `HtmlOptions::highlight`
+//! ```
+
+use crate::document::{ApplyError, Document};
+use crate::highlight::{self, Granularity, Span};
+use crate::macros::s;
+use crate::patch::Patch;
+use crate::render::{self, Row};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HtmlView {
+    /// old document on the left, new document on the right
+    SideBySide,
+    /// removed and added lines one after another, like `diff -u`
+    Unified,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HtmlOptions {
+    pub view: HtmlView,
+    pub context_lines: usize,
+    /// highlight the changed words or chars of the paired removed and added lines
+    pub highlight: Option<Granularity>,
+}
+
+impl Default for HtmlOptions {
+    fn default() -> Self {
+        Self {
+            view: HtmlView::SideBySide,
+            context_lines: 3,
+            highlight: None,
+        }
+    }
+}
+
+/// Escape the text to put in HTML.
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Render patch against old as an HTML table, it can be embedded in a page directly.
+/// The patch is checked against old first, see `Document::apply`.
+/// example:
+/// ```ignore
+/// let options = HtmlOptions {
+///     view: HtmlView::Unified,
+///     ..HtmlOptions::default()
+/// };
+/// let table = render_html(&old, &(new - old.clone()), options)?;
+/// ```
+pub fn render_html(
+    old: &Document,
+    patch: &Patch,
+    options: HtmlOptions,
+) -> Result<String, ApplyError> {
+    let (view, columns) = match options.view {
+        HtmlView::SideBySide => ("carbon-side-by-side", 4),
+        HtmlView::Unified => ("carbon-unified", 3),
+    };
+    let mut html = format!("<table class=\"carbon-diff {}\">\n", view);
+    for hunk in render::hunks(old, patch, options.context_lines)? {
+        html.push_str(&format!(
+            "<tbody>\n<tr class=\"carbon-hunk\"><td colspan=\"{}\">{}</td></tr>\n",
+            columns,
+            hunk.header()
+        ));
+        for row in &hunk.rows {
+            match (options.view, row) {
+                (
+                    HtmlView::SideBySide,
+                    Row::Common {
+                        old_line_num,
+                        new_line_num,
+                        line,
+                    },
+                ) => {
+                    html.push_str(&format!(
+                        "<tr class=\"carbon-context\">{}{}</tr>\n",
+                        cells("carbon-context", Some(*old_line_num), line, None),
+                        cells("carbon-context", Some(*new_line_num), line, None)
+                    ));
+                }
+                (HtmlView::SideBySide, Row::Change(change)) => {
+                    let spans = options
+                        .highlight
+                        .map(|granularity| highlight::change_spans(change, granularity));
+                    let removed_spans = |i: usize| spans.as_ref().map(|s| &s.removed[i][..]);
+                    let added_spans = |i: usize| spans.as_ref().map(|s| &s.added[i][..]);
+                    for i in 0..change.1.max(change.3) {
+                        let (class, left, right) = match (change.4.get(i), change.5.get(i)) {
+                            (Some(removed), Some(added)) => (
+                                "carbon-changed",
+                                cells(
+                                    "carbon-removed",
+                                    Some(change.0 + i),
+                                    removed,
+                                    removed_spans(i),
+                                ),
+                                cells("carbon-added", Some(change.2 + i), added, added_spans(i)),
+                            ),
+                            (Some(removed), None) => (
+                                "carbon-removed",
+                                cells(
+                                    "carbon-removed",
+                                    Some(change.0 + i),
+                                    removed,
+                                    removed_spans(i),
+                                ),
+                                cells("carbon-empty", None, "", None),
+                            ),
+                            (None, Some(added)) => (
+                                "carbon-added",
+                                cells("carbon-empty", None, "", None),
+                                cells("carbon-added", Some(change.2 + i), added, added_spans(i)),
+                            ),
+                            (None, None) => unreachable!(),
+                        };
+                        html.push_str(&format!("<tr class=\"{}\">{}{}</tr>\n", class, left, right));
+                    }
+                }
+                (
+                    HtmlView::Unified,
+                    Row::Common {
+                        old_line_num,
+                        new_line_num,
+                        line,
+                    },
+                ) => {
+                    html.push_str(&format!(
+                        "<tr class=\"carbon-context\">{}{}</tr>\n",
+                        line_num_cell(Some(*old_line_num)),
+                        cells("carbon-context", Some(*new_line_num), line, None)
+                    ));
+                }
+                (HtmlView::Unified, Row::Change(change)) => {
+                    let spans = options
+                        .highlight
+                        .map(|granularity| highlight::change_spans(change, granularity));
+                    // the removed and added lines are paired if both exist
+                    let changed = if change.1 > 0 && change.3 > 0 {
+                        " carbon-changed"
+                    } else {
+                        ""
+                    };
+                    for (i, line) in change.4.iter().enumerate() {
+                        html.push_str(&format!(
+                            "<tr class=\"carbon-removed{}\">{}{}</tr>\n",
+                            changed,
+                            line_num_cell(Some(change.0 + i)),
+                            cells(
+                                "carbon-removed",
+                                None,
+                                line,
+                                spans.as_ref().map(|s| &s.removed[i][..])
+                            )
+                        ));
+                    }
+                    for (i, line) in change.5.iter().enumerate() {
+                        html.push_str(&format!(
+                            "<tr class=\"carbon-added{}\">{}{}</tr>\n",
+                            changed,
+                            line_num_cell(None),
+                            cells(
+                                "carbon-added",
+                                Some(change.2 + i),
+                                line,
+                                spans.as_ref().map(|s| &s.added[i][..])
+                            )
+                        ));
+                    }
+                }
+            }
+        }
+        html.push_str("</tbody>\n");
+    }
+    html.push_str("</table>\n");
+    Ok(html)
+}
+
+/// The cell of a line number, it's 1-based.
+fn line_num_cell(line_num: Option<usize>) -> String {
+    match line_num {
+        Some(line_num) => format!("<td class=\"carbon-line-num\">{}</td>", line_num + 1),
+        None => s!("<td class=\"carbon-line-num\"></td>"),
+    }
+}
+
+/// The cells of a line number and a line, the changed Spans of the line are highlighted.
+fn cells(class: &str, line_num: Option<usize>, line: &str, spans: Option<&[Span]>) -> String {
+    let content = match spans {
+        Some(spans) => spans
+            .iter()
+            .map(|span| {
+                let text = escape(&line[span.start..span.end]);
+                if span.changed {
+                    format!("<span class=\"carbon-highlight\">{}</span>", text)
+                } else {
+                    text
+                }
+            })
+            .collect(),
+        None => escape(line),
+    };
+    format!(
+        "{}<td class=\"carbon-line {}\">{}</td>",
+        line_num_cell(line_num),
+        class,
+        content
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use self::super::*;
+
+    #[test]
+    // #[ignore]
+    fn test_escape() {
+        assert_eq!(
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&#39;s&lt;/a&gt;",
+            escape("<a href=\"x\">Tom & Jerry's</a>")
+        );
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_render_unified() {
+        let old = Document::from_content("A\nB\n<C>\n");
+        let new = Document::from_content("A\nX\n<C>\nD\n");
+        let patch = new - old.clone();
+        let options = HtmlOptions {
+            view: HtmlView::Unified,
+            context_lines: 1,
+            ..HtmlOptions::default()
+        };
+        assert_eq!(
+            "<table class=\"carbon-diff carbon-unified\">\n\
+             <tbody>\n\
+             <tr class=\"carbon-hunk\"><td colspan=\"3\">@@ -1,3 +1,4 @@</td></tr>\n\
+             <tr class=\"carbon-context\"><td class=\"carbon-line-num\">1</td>\
+             <td class=\"carbon-line-num\">1</td><td class=\"carbon-line carbon-context\">A</td></tr>\n\
+             <tr class=\"carbon-removed carbon-changed\"><td class=\"carbon-line-num\">2</td>\
+             <td class=\"carbon-line-num\"></td><td class=\"carbon-line carbon-removed\">B</td></tr>\n\
+             <tr class=\"carbon-added carbon-changed\"><td class=\"carbon-line-num\"></td>\
+             <td class=\"carbon-line-num\">2</td><td class=\"carbon-line carbon-added\">X</td></tr>\n\
+             <tr class=\"carbon-context\"><td class=\"carbon-line-num\">3</td>\
+             <td class=\"carbon-line-num\">3</td><td class=\"carbon-line carbon-context\">&lt;C&gt;</td></tr>\n\
+             <tr class=\"carbon-added\"><td class=\"carbon-line-num\"></td>\
+             <td class=\"carbon-line-num\">4</td><td class=\"carbon-line carbon-added\">D</td></tr>\n\
+             </tbody>\n\
+             </table>\n",
+            render_html(&old, &patch, options).unwrap()
+        );
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_render_side_by_side() {
+        let old = Document::from_content("A\nB\nC\n");
+        let new = Document::from_content("X\nY\nB\n");
+        let patch = new - old.clone();
+        let options = HtmlOptions {
+            context_lines: 0,
+            ..HtmlOptions::default()
+        };
+        let html = render_html(&old, &patch, options).unwrap();
+        assert_eq!(2, html.matches("<tbody>").count());
+        assert!(html.contains(
+            "<tr class=\"carbon-changed\"><td class=\"carbon-line-num\">1</td>\
+             <td class=\"carbon-line carbon-removed\">A</td><td class=\"carbon-line-num\">1</td>\
+             <td class=\"carbon-line carbon-added\">X</td></tr>\n"
+        ));
+        assert!(html.contains(
+            "<tr class=\"carbon-added\"><td class=\"carbon-line-num\"></td>\
+             <td class=\"carbon-line carbon-empty\"></td><td class=\"carbon-line-num\">2</td>\
+             <td class=\"carbon-line carbon-added\">Y</td></tr>\n"
+        ));
+        assert!(html.contains(
+            "<tr class=\"carbon-removed\"><td class=\"carbon-line-num\">3</td>\
+             <td class=\"carbon-line carbon-removed\">C</td><td class=\"carbon-line-num\"></td>\
+             <td class=\"carbon-line carbon-empty\"></td></tr>\n"
+        ));
+
+        // no change, no hunk
+        assert_eq!(
+            "<table class=\"carbon-diff carbon-side-by-side\">\n</table>\n",
+            render_html(&old, &Patch::from_vec(vec![]), HtmlOptions::default()).unwrap()
+        );
+
+        // the patch is not made from old
+        assert!(matches!(
+            render_html(&Document::from_content("A\n"), &patch, options),
+            Err(ApplyError::OutOfRange { .. })
+        ));
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_render_highlight() {
+        let old = Document::from_content("Hello wrold\n");
+        let new = Document::from_content("Hello <world>\n");
+        let patch = new - old.clone();
+        for view in [HtmlView::SideBySide, HtmlView::Unified] {
+            let options = HtmlOptions {
+                view,
+                highlight: Some(Granularity::Word),
+                ..HtmlOptions::default()
+            };
+            let html = render_html(&old, &patch, options).unwrap();
+            assert!(html.contains(
+                "<td class=\"carbon-line carbon-removed\">Hello \
+                 <span class=\"carbon-highlight\">wrold</span></td>"
+            ));
+            assert!(html.contains(
+                "<td class=\"carbon-line carbon-added\">Hello \
+                 <span class=\"carbon-highlight\">&lt;world&gt;</span></td>"
+            ));
+        }
+    }
+}
@@ -1,3 +1,15 @@
-mod document;
-mod patch;
+pub mod book;
+pub mod commit;
+pub mod diff;
+pub mod document;
+pub mod fuzzy;
+pub mod highlight;
+pub mod history;
+pub mod html;
 mod macros;
+pub mod merge;
+pub mod patch;
+pub mod refs;
+pub mod render;
+pub mod store;
+pub mod version;
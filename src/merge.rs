@@ -0,0 +1,589 @@
+//! This mod implements the three-way merge.
+//! Two versions are edited from a common base:
+//! ```text
+//! v0--->v1.1--->V2
+//!  |           ^
+//!  |--->v1.2---|
+//! ```
+//! Both sides are diffed against the base, the Changes of both sides are walked in order.
+//! Changes whose base ranges overlap or touch are grouped together,
+//! a group changed by only one side, or changed to the same lines by both sides, is merged,
+//! otherwise it's a conflict.
+
+use std::fmt;
+
+use crate::document::Document;
+use crate::macros::s;
+use crate::patch::Change;
+
+pub const OURS_MARKER: &str = "<<<<<<<";
+pub const BASE_MARKER: &str = "|||||||";
+pub const SEPARATOR_MARKER: &str = "=======";
+pub const THEIRS_MARKER: &str = ">>>>>>>";
+
+/// How to render conflicts with markers, the same as git's `merge.conflictStyle`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarkerStyle {
+    /// ours and theirs
+    Merge,
+    /// ours, base and theirs
+    Diff3,
+}
+
+/// How to resolve a conflict.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resolution {
+    /// accept ours, discard theirs
+    Ours,
+    /// accept theirs, discard ours
+    Theirs,
+    /// accept both, ours first
+    Union,
+    /// discard both, keep base
+    Base,
+    /// replace the conflict with the given lines
+    Lines(Vec<String>),
+}
+
+impl Resolution {
+    fn apply(&self, conflict: &MergeConflict) -> Vec<String> {
+        match self {
+            Resolution::Ours => conflict.ours.clone(),
+            Resolution::Theirs => conflict.theirs.clone(),
+            Resolution::Union => [&conflict.ours[..], &conflict.theirs[..]].concat(),
+            Resolution::Base => conflict.base.clone(),
+            Resolution::Lines(lines) => lines.clone(),
+        }
+    }
+}
+
+/// How to resolve all conflicts of a MergeResult, see `MergeResult::resolve`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResolutionStrategy {
+    Ours,
+    Theirs,
+    Union,
+    /// one Resolution for each conflict, in order
+    Choices(Vec<Resolution>),
+}
+
+/// The number of choices doesn't match the number of conflicts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolveError {
+    pub conflicts: usize,
+    pub choices: usize,
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} choices are given for {} conflicts",
+            self.choices, self.conflicts
+        )
+    }
+}
+
+impl std::error::Error for ResolveError {}
+
+/// Both sides edit the same part of base differently.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergeConflict {
+    /// line number of the conflict in base document
+    pub base_line_num: usize,
+    pub base: Vec<String>,
+    pub ours: Vec<String>,
+    pub theirs: Vec<String>,
+}
+
+/// A merged document is made of regions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MergeRegion {
+    /// The lines are merged without conflict.
+    Resolved(Vec<String>),
+    Conflict(MergeConflict),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergeResult {
+    pub regions: Vec<MergeRegion>,
+    /// see Document
+    pub no_newline_at_eof: bool,
+}
+
+impl MergeResult {
+    pub fn is_clean(&self) -> bool {
+        self.conflicts().is_empty()
+    }
+
+    pub fn conflicts(&self) -> Vec<&MergeConflict> {
+        self.regions
+            .iter()
+            .filter_map(|region| match region {
+                MergeRegion::Conflict(conflict) => Some(conflict),
+                MergeRegion::Resolved(_) => None,
+            })
+            .collect()
+    }
+
+    /// Render the merged Document, conflicts are marked like git:
+    /// ```text
+    /// <<<<<<< ours
+    /// X
+    /// ||||||| base
+    /// B
+    /// =======
+    /// Y
+    /// >>>>>>> theirs
+    /// ```
+    /// The base part is only rendered in Diff3 style.
+    /// The maintainer edits the rendered Document to resolve the conflicts,
+    /// see `find_markers`.
+    pub fn render(&self, style: MarkerStyle, ours_label: &str, theirs_label: &str) -> Document {
+        let mut lines = vec![];
+        for region in &self.regions {
+            match region {
+                MergeRegion::Resolved(resolved) => lines.extend_from_slice(resolved),
+                MergeRegion::Conflict(conflict) => {
+                    lines.push(format!("{} {}", OURS_MARKER, ours_label));
+                    lines.extend_from_slice(&conflict.ours);
+                    if style == MarkerStyle::Diff3 {
+                        lines.push(format!("{} base", BASE_MARKER));
+                        lines.extend_from_slice(&conflict.base);
+                    }
+                    lines.push(s!(SEPARATOR_MARKER));
+                    lines.extend_from_slice(&conflict.theirs);
+                    lines.push(format!("{} {}", THEIRS_MARKER, theirs_label));
+                }
+            }
+        }
+        Document::from_vec(lines).with_no_newline_at_eof(self.no_newline_at_eof)
+    }
+
+    /// Resolve all conflicts by the strategy, return the merged Document.
+    /// example:
+    /// ```ignore
+    /// let result = Document::merge3(&base, &main, &suggestion);
+    /// let strategy = ResolutionStrategy::Choices(vec![Resolution::Ours, Resolution::Theirs]);
+    /// let doc = result.resolve(&strategy)?;
+    /// ```
+    pub fn resolve(&self, strategy: &ResolutionStrategy) -> Result<Document, ResolveError> {
+        let conflicts = self.conflicts().len();
+        let choices = match strategy {
+            ResolutionStrategy::Ours => vec![Resolution::Ours; conflicts],
+            ResolutionStrategy::Theirs => vec![Resolution::Theirs; conflicts],
+            ResolutionStrategy::Union => vec![Resolution::Union; conflicts],
+            ResolutionStrategy::Choices(choices) => choices.clone(),
+        };
+        if choices.len() != conflicts {
+            return Err(ResolveError {
+                conflicts,
+                choices: choices.len(),
+            });
+        }
+        let mut choices = choices.iter();
+        let mut lines = vec![];
+        for region in &self.regions {
+            match region {
+                MergeRegion::Resolved(resolved) => lines.extend_from_slice(resolved),
+                MergeRegion::Conflict(conflict) => {
+                    lines.extend(choices.next().unwrap().apply(conflict))
+                }
+            }
+        }
+        Ok(Document::from_vec(lines).with_no_newline_at_eof(self.no_newline_at_eof))
+    }
+
+    /// The merged Document, None if there are conflicts.
+    pub fn document(&self) -> Option<Document> {
+        let mut lines = vec![];
+        for region in &self.regions {
+            match region {
+                MergeRegion::Resolved(resolved) => lines.extend_from_slice(resolved),
+                MergeRegion::Conflict(_) => return None,
+            }
+        }
+        Some(Document::from_vec(lines).with_no_newline_at_eof(self.no_newline_at_eof))
+    }
+}
+
+/// Merge ours and theirs, both are edited from base.
+pub fn merge3(base: &Document, ours: &Document, theirs: &Document) -> MergeResult {
+    let base_vec_ref = base.as_vec_ref();
+    let ours_patch = ours.clone() - base.clone();
+    let theirs_patch = theirs.clone() - base.clone();
+    let ours_changes = ours_patch.as_vec_ref();
+    let theirs_changes = theirs_patch.as_vec_ref();
+
+    let mut regions = vec![];
+    let mut resolved = vec![];
+    let mut base_line_num = 0;
+    let mut ours_idx = 0;
+    let mut theirs_idx = 0;
+    while ours_idx < ours_changes.len() || theirs_idx < theirs_changes.len() {
+        // step 1: group the changes overlapping or touching each other.
+        let mut ours_group: Vec<&Change> = vec![];
+        let mut theirs_group: Vec<&Change> = vec![];
+        let first = match (ours_changes.get(ours_idx), theirs_changes.get(theirs_idx)) {
+            (Some(o), Some(t)) => o.0 <= t.0,
+            (Some(_), None) => true,
+            _ => false,
+        };
+        let (start, mut end) = if first {
+            ours_group.push(&ours_changes[ours_idx]);
+            ours_idx += 1;
+            (ours_group[0].0, ours_group[0].0 + ours_group[0].1)
+        } else {
+            theirs_group.push(&theirs_changes[theirs_idx]);
+            theirs_idx += 1;
+            (theirs_group[0].0, theirs_group[0].0 + theirs_group[0].1)
+        };
+        loop {
+            if let Some(change) = ours_changes.get(ours_idx).filter(|c| c.0 <= end) {
+                end = end.max(change.0 + change.1);
+                ours_group.push(change);
+                ours_idx += 1;
+            } else if let Some(change) = theirs_changes.get(theirs_idx).filter(|c| c.0 <= end) {
+                end = end.max(change.0 + change.1);
+                theirs_group.push(change);
+                theirs_idx += 1;
+            } else {
+                break;
+            }
+        }
+
+        // step 2: the common lines before the group.
+        resolved.extend_from_slice(&base_vec_ref[base_line_num..start]);
+        base_line_num = end;
+
+        // step 3: merge the group.
+        let ours_lines = apply_range(base_vec_ref, &ours_group, start, end);
+        let theirs_lines = apply_range(base_vec_ref, &theirs_group, start, end);
+        if theirs_group.is_empty() || ours_lines == theirs_lines {
+            resolved.extend(ours_lines);
+        } else if ours_group.is_empty() {
+            resolved.extend(theirs_lines);
+        } else {
+            if !resolved.is_empty() {
+                regions.push(MergeRegion::Resolved(std::mem::take(&mut resolved)));
+            }
+            regions.push(MergeRegion::Conflict(MergeConflict {
+                base_line_num: start,
+                base: base_vec_ref[start..end].to_vec(),
+                ours: ours_lines,
+                theirs: theirs_lines,
+            }));
+        }
+    }
+    resolved.extend_from_slice(&base_vec_ref[base_line_num..]);
+    if !resolved.is_empty() {
+        regions.push(MergeRegion::Resolved(resolved));
+    }
+
+    let no_newline_at_eof = ours_patch
+        .no_newline_at_eof()
+        .or_else(|| theirs_patch.no_newline_at_eof())
+        .unwrap_or_else(|| base.no_newline_at_eof());
+    MergeResult {
+        regions,
+        no_newline_at_eof,
+    }
+}
+
+/// An unresolved conflict found in a Document rendered with markers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MarkedConflict {
+    /// line number of the `<<<<<<<` marker
+    pub start: usize,
+    /// line number of the `>>>>>>>` marker
+    pub end: usize,
+    pub ours: Vec<String>,
+    /// None if it's not rendered in Diff3 style
+    pub base: Option<Vec<String>>,
+    pub theirs: Vec<String>,
+}
+
+/// A marker is out of place.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MarkerError {
+    /// line number of the marker, it's shown 1-based
+    pub line_num: usize,
+    pub message: String,
+}
+
+impl fmt::Display for MarkerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line_num + 1, self.message)
+    }
+}
+
+impl std::error::Error for MarkerError {}
+
+/// Check whether a line is a marker: the marker alone or followed by a space and a label.
+fn is_marker(line: &str, marker: &str) -> bool {
+    line.strip_prefix(marker)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with(' '))
+}
+
+/// Find the unresolved conflicts in a Document rendered with markers.
+/// A `=======` line outside of conflicts is normal text, it's a heading underline in markdown,
+/// the other markers outside of conflicts, or unclosed conflicts, are errors.
+pub fn find_markers(doc: &Document) -> Result<Vec<MarkedConflict>, MarkerError> {
+    let error = |line_num: usize, message: &str| MarkerError {
+        line_num,
+        message: s!(message),
+    };
+    let mut conflicts = vec![];
+    // the conflict being parsed, and the part being filled: 0 ours, 1 base, 2 theirs
+    let mut current: Option<(MarkedConflict, u8)> = None;
+    for (line_num, line) in doc.as_vec_ref().iter().enumerate() {
+        let marker = [OURS_MARKER, BASE_MARKER, SEPARATOR_MARKER, THEIRS_MARKER]
+            .iter()
+            .position(|marker| is_marker(line, marker));
+        current = match (current, marker) {
+            (None, Some(0)) => Some((
+                MarkedConflict {
+                    start: line_num,
+                    end: line_num,
+                    ours: vec![],
+                    base: None,
+                    theirs: vec![],
+                },
+                0,
+            )),
+            (None, Some(2)) | (None, None) => None,
+            (None, Some(_)) => return Err(error(line_num, "marker outside of conflict")),
+            (Some((mut conflict, 0)), Some(1)) => {
+                conflict.base = Some(vec![]);
+                Some((conflict, 1))
+            }
+            (Some((conflict, 0)), Some(2)) | (Some((conflict, 1)), Some(2)) => Some((conflict, 2)),
+            (Some((mut conflict, 2)), Some(3)) => {
+                conflict.end = line_num;
+                conflicts.push(conflict);
+                None
+            }
+            (Some((mut conflict, part)), None) => {
+                match part {
+                    0 => conflict.ours.push(line.clone()),
+                    1 => conflict.base.as_mut().unwrap().push(line.clone()),
+                    _ => conflict.theirs.push(line.clone()),
+                }
+                Some((conflict, part))
+            }
+            (Some(_), Some(_)) => return Err(error(line_num, "marker out of order")),
+        };
+    }
+    match current {
+        Some((conflict, _)) => Err(error(conflict.start, "conflict is not closed")),
+        None => Ok(conflicts),
+    }
+}
+
+/// Apply the changes to the lines of base in range [start, end),
+/// the changes must be sorted and inside the range.
+fn apply_range(base: &[String], changes: &[&Change], start: usize, end: usize) -> Vec<String> {
+    let mut lines = vec![];
+    let mut base_line_num = start;
+    for change in changes {
+        lines.extend_from_slice(&base[base_line_num..change.0]);
+        lines.extend_from_slice(&change.5);
+        base_line_num = change.0 + change.1;
+    }
+    lines.extend_from_slice(&base[base_line_num..end]);
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use self::super::*;
+    use crate::macros::s;
+
+    fn doc(content: &str) -> Document {
+        Document::from_content(content)
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_merge_clean() {
+        let base = doc("A\nB\nC\nD\nE\nF\n");
+        let ours = doc("A\nX\nC\nD\nE\nF\n");
+        let theirs = doc("A\nB\nC\nD\nY\nF\nZ\n");
+        let result = merge3(&base, &ours, &theirs);
+        assert!(result.is_clean());
+        assert_eq!(Some(doc("A\nX\nC\nD\nY\nF\nZ\n")), result.document());
+
+        // both sides make the same change
+        let result = merge3(&base, &ours, &ours);
+        assert_eq!(Some(ours), result.document());
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_merge_conflict() {
+        let base = doc("A\nB\nC\nD\nE\nF\n");
+        let ours = doc("A\nX\nC\nD\nE\nF\n");
+        let theirs = doc("A\nY\nC\nD\nE\nG\n");
+        let result = merge3(&base, &ours, &theirs);
+        assert!(!result.is_clean());
+        assert_eq!(None, result.document());
+        assert_eq!(
+            vec![
+                MergeRegion::Resolved(vec![s!("A")]),
+                MergeRegion::Conflict(MergeConflict {
+                    base_line_num: 1,
+                    base: vec![s!("B")],
+                    ours: vec![s!("X")],
+                    theirs: vec![s!("Y")],
+                }),
+                MergeRegion::Resolved(vec![s!("C"), s!("D"), s!("E"), s!("G")]),
+            ],
+            result.regions
+        );
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_merge_touching() {
+        // ours changes B, theirs inserts right after B.
+        let base = doc("A\nB\nC\n");
+        let ours = doc("A\nX\nC\n");
+        let theirs = doc("A\nB\nY\nC\n");
+        let result = merge3(&base, &ours, &theirs);
+        assert_eq!(
+            vec![&MergeConflict {
+                base_line_num: 1,
+                base: vec![s!("B")],
+                ours: vec![s!("X")],
+                theirs: vec![s!("B"), s!("Y")],
+            }],
+            result.conflicts()
+        );
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_resolve() {
+        let base = doc("A\nB\nC\nD\nE\nF\nG\n");
+        let ours = doc("A\nX\nC\nD\nE\nZ\nG\n");
+        let theirs = doc("A\nY\nC\nW\nE\nF\nG\n");
+        let result = merge3(&base, &ours, &theirs);
+        assert_eq!(1, result.conflicts().len());
+
+        for (strategy, content) in &[
+            (ResolutionStrategy::Ours, "A\nX\nC\nW\nE\nZ\nG\n"),
+            (ResolutionStrategy::Theirs, "A\nY\nC\nW\nE\nZ\nG\n"),
+            (ResolutionStrategy::Union, "A\nX\nY\nC\nW\nE\nZ\nG\n"),
+            (
+                ResolutionStrategy::Choices(vec![Resolution::Base]),
+                "A\nB\nC\nW\nE\nZ\nG\n",
+            ),
+            (
+                ResolutionStrategy::Choices(vec![Resolution::Lines(vec![s!("XY")])]),
+                "A\nXY\nC\nW\nE\nZ\nG\n",
+            ),
+        ] {
+            assert_eq!(doc(content), result.resolve(strategy).unwrap());
+        }
+
+        assert_eq!(
+            ResolveError {
+                conflicts: 1,
+                choices: 2
+            },
+            result
+                .resolve(&ResolutionStrategy::Choices(vec![
+                    Resolution::Ours,
+                    Resolution::Theirs
+                ]))
+                .unwrap_err()
+        );
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_render() {
+        let base = doc("A\nB\nC\n");
+        let result = merge3(&base, &doc("A\nX\nC\n"), &doc("A\nY\nC\n"));
+        let merged = result.render(MarkerStyle::Merge, "main", "alice");
+        assert_eq!(
+            "A\n<<<<<<< main\nX\n=======\nY\n>>>>>>> alice\nC\n",
+            merged.to_content()
+        );
+        let conflicts = find_markers(&merged).unwrap();
+        assert_eq!(
+            vec![MarkedConflict {
+                start: 1,
+                end: 5,
+                ours: vec![s!("X")],
+                base: None,
+                theirs: vec![s!("Y")],
+            }],
+            conflicts
+        );
+
+        let merged = result.render(MarkerStyle::Diff3, "main", "alice");
+        assert_eq!(
+            "A\n<<<<<<< main\nX\n||||||| base\nB\n=======\nY\n>>>>>>> alice\nC\n",
+            merged.to_content()
+        );
+        let conflicts = find_markers(&merged).unwrap();
+        assert_eq!(Some(vec![s!("B")]), conflicts[0].base);
+
+        // a clean result has no markers
+        let result = merge3(&base, &doc("A\nX\nC\n"), &base);
+        let merged = result.render(MarkerStyle::Diff3, "main", "alice");
+        assert_eq!(result.document(), Some(merged.clone()));
+        assert!(find_markers(&merged).unwrap().is_empty());
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_find_markers() {
+        // a markdown heading underline is not a marker
+        assert!(find_markers(&doc("Title\n=======\n<<<<<<<<\n"))
+            .unwrap()
+            .is_empty());
+
+        for (content, line_num) in &[
+            (">>>>>>> alice\n", 0),
+            ("A\n<<<<<<< main\nX\n=======\nY\n", 1),
+            ("<<<<<<< main\nX\n>>>>>>> alice\n", 2),
+            ("<<<<<<< main\n=======\n||||||| base\n>>>>>>> alice\n", 2),
+        ] {
+            assert_eq!(*line_num, find_markers(&doc(content)).unwrap_err().line_num);
+        }
+        assert_eq!(
+            "line 1: marker outside of conflict",
+            find_markers(&doc(">>>>>>> alice\n"))
+                .unwrap_err()
+                .to_string()
+        );
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_merge_fixtures() {
+        let base = Document::from_fs(s!("tests/base"));
+        let one = Document::from_fs(s!("tests/change_one"));
+        let all = Document::from_fs(s!("tests/change_all"));
+        assert_eq!(Some(one.clone()), merge3(&base, &one, &base).document());
+        assert_eq!(Some(one.clone()), merge3(&base, &base, &one).document());
+        assert_eq!(1, merge3(&base, &one, &all).conflicts().len());
+    }
+
+    proptest::proptest! {
+        /// merge3(base, x, base) == merge3(base, base, x) == merge3(base, x, x) == x
+        #[test]
+        fn prop_merge_one_side(
+            base in "[a-e\n]{0,60}",
+            new in "[a-e\n]{0,60}",
+        ) {
+            let base = Document::from_content(&base);
+            let new = Document::from_content(&new);
+            proptest::prop_assert_eq!(Some(new.clone()), merge3(&base, &new, &base).document());
+            proptest::prop_assert_eq!(Some(new.clone()), merge3(&base, &base, &new).document());
+            proptest::prop_assert_eq!(Some(new.clone()), merge3(&base, &new, &new).document());
+        }
+    }
+}
@@ -3,10 +3,15 @@
 use std::fmt;
 use std::fs;
 use std::fs::File;
-use std::io::{BufRead, BufReader, Error, Write};
+use std::io::{BufRead, BufReader, Error, ErrorKind, Result, Write};
 use std::ops::{Add, Sub};
 
+use crate::diff;
 use crate::document::Document;
+use crate::highlight::{self, ChangeSpans, Granularity};
+use crate::macros::s;
+use crate::render::{self, Row};
+use crate::store::ObjectStore;
 
 /// An edit makes a Change.
 /// 0. line_num in old document
@@ -17,9 +22,29 @@ use crate::document::Document;
 /// 5. added lines
 pub type Change = (usize, usize, usize, usize, Vec<String>, Vec<String>);
 
-/// A Patch consits of a list of Changes
-#[derive(Debug)]
-pub struct Patch(Vec<Change>);
+/// The version of patch encoding, see `Patch::encode`.
+pub const PATCH_VERSION: usize = 1;
+
+/// Two Changes of two Patches against the same base document collide:
+/// their old line ranges overlap or touch.
+/// The line numbers and added lines of both sides are in the Changes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    pub ours: Change,
+    pub theirs: Change,
+}
+
+/// Check whether the old line ranges of two Changes overlap or touch,
+/// an insertion touches the Change before and after it.
+pub fn touches(a: &Change, b: &Change) -> bool {
+    a.0 <= b.0 + b.1 && b.0 <= a.0 + a.1
+}
+
+/// A Patch consits of a list of Changes.
+/// 0. the changes
+/// 1. the new `no_newline_at_eof` state of document if it's changed, see Document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Patch(Vec<Change>, Option<bool>);
 
 impl Patch {
     /// create Pathc from changes' vec
@@ -30,7 +55,17 @@ impl Patch {
     /// Patch::from_vec(changes)
     /// ```
     pub fn from_vec(vec: Vec<Change>) -> Self {
-        Self(vec)
+        Self(vec, None)
+    }
+
+    /// Set the change of `no_newline_at_eof` state of document.
+    pub fn with_no_newline_at_eof(self, no_newline_at_eof: Option<bool>) -> Self {
+        Self(self.0, no_newline_at_eof)
+    }
+
+    /// The new `no_newline_at_eof` state of document, None if it's not changed.
+    pub fn no_newline_at_eof(&self) -> Option<bool> {
+        self.1
     }
 
     /// This method is useful to get ref of inner Vec
@@ -43,24 +78,563 @@ impl Patch {
     /// }
     /// ```
     pub fn as_vec_ref(&self) -> &Vec<Change> {
-        let Patch(vec) = self;
+        let Patch(vec, _) = self;
         vec
     }
 
-    /// Write Patch object to file system.
+    /// Find the conflicts of two Patches against the same base document,
+    /// self is ours and other is theirs.
+    /// Every pair of Changes that overlap or touch is reported, ordered by line number.
+    /// example:
+    /// ```ignore
+    /// let patch_main = main - base.clone();
+    /// let patch_other = other - base;
+    /// for conflict in patch_other.conflicts_with(&patch_main) {
+    ///     println!("@ {}: {:?} vs {:?}", conflict.ours.0, conflict.ours.5, conflict.theirs.5);
+    /// }
+    /// ```
+    pub fn conflicts_with(&self, other: &Self) -> Vec<Conflict> {
+        let theirs = other.as_vec_ref();
+        let mut conflicts = vec![];
+        // the first Change of theirs that may touch the current Change of ours
+        let mut first = 0;
+        for ours in self.as_vec_ref() {
+            while first < theirs.len() && theirs[first].0 + theirs[first].1 < ours.0 {
+                first += 1;
+            }
+            for theirs in theirs[first..].iter().take_while(|c| touches(ours, c)) {
+                conflicts.push(Conflict {
+                    ours: ours.clone(),
+                    theirs: theirs.clone(),
+                });
+            }
+        }
+        conflicts
+    }
+
+    /// Rebase self onto upstream, both Patches are against the same base document.
+    /// The line numbers of each Change are shifted past the insertions and deletions of upstream,
+    /// so that `base + upstream + rebased` applies both Patches.
+    /// A Change that overlaps or touches a Change of upstream is a Conflict, see `conflicts_with`.
+    /// example:
+    /// ```ignore
+    /// let upstream = main.clone() - release.clone();
+    /// let suggestion = suggestion - release;
+    /// let new_main = main + suggestion.rebase_onto(&upstream)?;
+    /// ```
+    #[allow(clippy::result_large_err)]
+    pub fn rebase_onto(&self, upstream: &Self) -> std::result::Result<Self, Conflict> {
+        let theirs = upstream.as_vec_ref();
+        let mut changes = vec![];
+        // the count of lines upstream adds before the current Change, minus the count it removes
+        let mut offset: isize = 0;
+        let mut idx = 0;
+        for ours in self.as_vec_ref() {
+            while idx < theirs.len() && theirs[idx].0 + theirs[idx].1 < ours.0 {
+                offset += theirs[idx].3 as isize - theirs[idx].1 as isize;
+                idx += 1;
+            }
+            if let Some(theirs) = theirs.get(idx).filter(|theirs| touches(ours, theirs)) {
+                return Err(Conflict {
+                    ours: ours.clone(),
+                    theirs: theirs.clone(),
+                });
+            }
+            let mut change = ours.clone();
+            change.0 = (change.0 as isize + offset) as usize;
+            change.2 = (change.2 as isize + offset) as usize;
+            changes.push(change);
+        }
+        // both set the state only when it differs from base, so they don't disagree
+        let no_newline_at_eof = self.1.filter(|_| upstream.1.is_none());
+        Ok(Self::from_vec(changes).with_no_newline_at_eof(no_newline_at_eof))
+    }
+
+    /// Invert the Patch: `old + patch == new <=> new + patch.invert() == old`.
+    /// The old and new line numbers, counts and lines of each Change are swapped.
+    pub fn invert(&self) -> Self {
+        let changes = self
+            .as_vec_ref()
+            .iter()
+            .map(|c| (c.2, c.3, c.0, c.1, c.5.clone(), c.4.clone()))
+            .collect();
+        Self::from_vec(changes).with_no_newline_at_eof(self.1.map(|eof| !eof))
+    }
+
+    /// Compose self and next into one Patch: `doc + self.compose(&next) == doc + self + next`.
+    /// It's computed from the Changes only, the intermediate Document is not needed.
+    /// The Changes are walked in the line numbers of the intermediate Document,
+    /// where the added lines of self and the removed lines of next are placed,
+    /// the ranges overlapping or touching each other are grouped into one Change.
+    /// example:
+    /// ```ignore
+    /// // squash the history of a contributor
+    /// let squashed = patches.iter().fold(Patch::from_vec(vec![]), |p, next| p.compose(next));
+    /// ```
+    pub fn compose(&self, next: &Self) -> Self {
+        let firsts = self.as_vec_ref();
+        let seconds = next.as_vec_ref();
+        let mut changes = vec![];
+        // the offsets of line numbers made by the Changes before the current group
+        let mut first_offset: isize = 0;
+        let mut second_offset: isize = 0;
+        let mut first_idx = 0;
+        let mut second_idx = 0;
+        while first_idx < firsts.len() || second_idx < seconds.len() {
+            // step 1: group the ranges overlapping or touching each other,
+            // in the intermediate Document.
+            let first_range = |c: &Change| (c.2, c.2 + c.3);
+            let second_range = |c: &Change| (c.0, c.0 + c.1);
+            let (start, mut end) = match (firsts.get(first_idx), seconds.get(second_idx)) {
+                (Some(f), Some(s)) if f.2 <= s.0 => first_range(f),
+                (Some(f), None) => first_range(f),
+                (_, Some(s)) => second_range(s),
+                (None, None) => unreachable!(),
+            };
+            let (first_start, second_start) = (first_idx, second_idx);
+            loop {
+                if let Some(f) = firsts.get(first_idx).filter(|f| f.2 <= end) {
+                    end = end.max(first_range(f).1);
+                    first_idx += 1;
+                } else if let Some(s) = seconds.get(second_idx).filter(|s| s.0 <= end) {
+                    end = end.max(second_range(s).1);
+                    second_idx += 1;
+                } else {
+                    break;
+                }
+            }
+            let first_group = &firsts[first_start..first_idx];
+            let second_group = &seconds[second_start..second_idx];
+
+            // step 2: the lines of the intermediate Document in the group,
+            // every line is added by self or removed by next.
+            let mut lines: Vec<Option<&String>> = vec![None; end - start];
+            for f in first_group {
+                for (i, line) in f.5.iter().enumerate() {
+                    lines[f.2 + i - start] = Some(line);
+                }
+            }
+            for s in second_group {
+                for (i, line) in s.4.iter().enumerate() {
+                    lines[s.0 + i - start] = Some(line);
+                }
+            }
+
+            // step 3: undo self to get the old lines, apply next to get the new lines.
+            let walk = |group: &[Change],
+                        range: fn(&Change) -> (usize, usize),
+                        pick: fn(&Change) -> &Vec<String>| {
+                let mut result = vec![];
+                let mut group = group.iter().peekable();
+                let mut pos = start;
+                loop {
+                    while let Some(change) = group.next_if(|c| range(c).0 == pos) {
+                        result.extend_from_slice(pick(change));
+                        pos = range(change).1;
+                    }
+                    if pos >= end {
+                        break;
+                    }
+                    result.push(lines[pos - start].unwrap().clone());
+                    pos += 1;
+                }
+                result
+            };
+            let removed_lines = walk(first_group, first_range, |c| &c.4);
+            let added_lines = walk(second_group, second_range, |c| &c.5);
+            if removed_lines != added_lines {
+                changes.push((
+                    (start as isize - first_offset) as usize,
+                    removed_lines.len(),
+                    (start as isize + second_offset) as usize,
+                    added_lines.len(),
+                    removed_lines,
+                    added_lines,
+                ));
+            }
+            for f in first_group {
+                first_offset += f.3 as isize - f.1 as isize;
+            }
+            for s in second_group {
+                second_offset += s.3 as isize - s.1 as isize;
+            }
+        }
+        // both set the state only when it's changed, so two changes cancel out
+        let no_newline_at_eof = match (self.1, next.1) {
+            (Some(_), Some(_)) => None,
+            (first, second) => second.or(first),
+        };
+        Self::from_vec(changes).with_no_newline_at_eof(no_newline_at_eof)
+    }
+
+    /// Format the Patch as a unified diff, the same as `diff -u --label a --label b`,
+    /// old and new are the documents the Patch is made from, they provide the context lines.
+    /// An empty string is returned if there's no change.
+    /// example:
+    /// ```ignore
+    /// let patch = new.clone() - old.clone();
+    /// print!("{}", patch.to_unified(&old, &new, 3));
+    /// ```
+    pub fn to_unified(&self, old: &Document, new: &Document, context_lines: usize) -> String {
+        self.to_unified_with_labels(old, new, context_lines, "a", "b")
+    }
+
+    /// Format the Patch as a unified diff with the labels of old and new in the headers,
+    /// see `Patch::to_unified`.
+    pub fn to_unified_with_labels(
+        &self,
+        old: &Document,
+        new: &Document,
+        context_lines: usize,
+        old_label: &str,
+        new_label: &str,
+    ) -> String {
+        let old_lines = old.as_vec_ref();
+        let new_lines = new.as_vec_ref();
+        let patch = Patch::from_vec(self.unified_changes(old, new));
+        if patch.as_vec_ref().is_empty() {
+            return s!("");
+        }
+        let mut content = format!("--- {}\n+++ {}\n", old_label, new_label);
+        let push_line = |content: &mut String, prefix: char, line: &str, no_newline: bool| {
+            content.push(prefix);
+            content.push_str(line);
+            content.push('\n');
+            if no_newline {
+                content.push_str("\\ No newline at end of file\n");
+            }
+        };
+        let old_eof = |line_num: usize| old.no_newline_at_eof() && line_num + 1 == old_lines.len();
+        let new_eof = |line_num: usize| new.no_newline_at_eof() && line_num + 1 == new_lines.len();
+
+        for hunk in render::group(old, &patch, context_lines) {
+            content.push_str(&hunk.header());
+            content.push('\n');
+            for row in &hunk.rows {
+                match row {
+                    Row::Common {
+                        old_line_num, line, ..
+                    } => push_line(&mut content, ' ', line, old_eof(*old_line_num)),
+                    Row::Change(change) => {
+                        for (line_num, line) in (change.0..).zip(&change.4) {
+                            push_line(&mut content, '-', line, old_eof(line_num));
+                        }
+                        for (line_num, line) in (change.2..).zip(&change.5) {
+                            push_line(&mut content, '+', line, new_eof(line_num));
+                        }
+                    }
+                }
+            }
+        }
+        content
+    }
+
+    /// Parse a unified diff of one document into a Patch, the output of `diff -u` or `git diff`.
+    /// The lines before the first hunk, like `diff --git` and `---`/`+++` headers, are skipped.
+    /// Each hunk header is validated: the line counts must match the hunk,
+    /// the hunks must be in order and the new line numbers must follow the previous hunks.
+    /// example:
+    /// ```ignore
+    /// let patch = Patch::from_unified(&fs::read_to_string("suggestion.diff")?)?;
+    /// let new = doc.apply(&patch)?;
+    /// ```
+    pub fn from_unified(content: &str) -> Result<Self> {
+        let invalid = |line_num: usize, msg: &str| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("line {}: {}", line_num + 1, msg),
+            )
+        };
+        // a `\r` at the end of a line belongs to the line, see Document
+        let mut lines: Vec<&str> = content.split('\n').collect();
+        if lines.last() == Some(&"") {
+            lines.pop();
+        }
+        let mut idx = lines
+            .iter()
+            .position(|line| line.starts_with("@@"))
+            .unwrap_or(lines.len());
+        let mut changes: Vec<Change> = vec![];
+        let mut old_no_newline = false;
+        let mut new_no_newline = false;
+        // the end of the previous hunk in old document, and the offset made by the Changes
+        let mut pre_line_num = 0;
+        let mut offset: isize = 0;
+        while idx < lines.len() {
+            let header = lines[idx];
+            let (old_start, old_count, new_start, new_count) = parse_hunk_header(header)
+                .ok_or_else(|| invalid(idx, &format!("invalid hunk header: {:?}", header)))?;
+            if old_start < pre_line_num {
+                return Err(invalid(idx, "hunk overlaps the previous hunk"));
+            }
+            if new_start as isize != old_start as isize + offset {
+                return Err(invalid(
+                    idx,
+                    "new line number doesn't follow the previous hunks",
+                ));
+            }
+            idx += 1;
+
+            // the lines of the hunk, a Change is made of the `-` and `+` lines between ` ` lines
+            let (mut old_line_num, mut new_line_num) = (old_start, new_start);
+            let mut change: Change = (old_start, 0, new_start, 0, vec![], vec![]);
+            while old_line_num < old_start + old_count || new_line_num < new_start + new_count {
+                let line = *lines
+                    .get(idx)
+                    .ok_or_else(|| invalid(idx, "hunk is truncated"))?;
+                let (kind, text) = match line.chars().next() {
+                    // some tools strip the space of an empty context line
+                    None => (' ', ""),
+                    Some(kind) => (kind, &line[kind.len_utf8()..]),
+                };
+                match kind {
+                    ' ' | '-' if old_line_num == old_start + old_count => {
+                        return Err(invalid(idx, "too many old lines in hunk"))
+                    }
+                    ' ' | '+' if new_line_num == new_start + new_count => {
+                        return Err(invalid(idx, "too many new lines in hunk"))
+                    }
+                    ' ' => {
+                        if change.1 > 0 || change.3 > 0 {
+                            changes.push(change);
+                        }
+                        old_line_num += 1;
+                        new_line_num += 1;
+                        change = (old_line_num, 0, new_line_num, 0, vec![], vec![]);
+                    }
+                    '-' => {
+                        change.1 += 1;
+                        change.4.push(s!(text));
+                        old_line_num += 1;
+                    }
+                    '+' => {
+                        change.3 += 1;
+                        change.5.push(s!(text));
+                        new_line_num += 1;
+                    }
+                    _ => return Err(invalid(idx, &format!("invalid hunk line: {:?}", line))),
+                }
+                idx += 1;
+                if lines.get(idx).is_some_and(|line| line.starts_with('\\')) {
+                    old_no_newline |= kind != '+';
+                    new_no_newline |= kind != '-';
+                    idx += 1;
+                }
+            }
+            if change.1 > 0 || change.3 > 0 {
+                changes.push(change);
+            }
+            pre_line_num = old_line_num;
+            offset = new_line_num as isize - old_line_num as isize;
+            match lines.get(idx) {
+                Some(line) if !line.starts_with("@@") => {
+                    return Err(invalid(idx, &format!("unexpected line: {:?}", line)))
+                }
+                _ => {}
+            }
+        }
+        let no_newline_at_eof = if old_no_newline != new_no_newline {
+            Some(new_no_newline)
+        } else {
+            None
+        };
+        Ok(Self::from_vec(changes).with_no_newline_at_eof(no_newline_at_eof))
+    }
+
+    /// The Changes shown in a unified diff.
+    /// GNU diff compares lines with their `\n`, a last line without `\n` only equals
+    /// the last line without `\n` of the other document,
+    /// so a common line pairing it with another line is a changed line.
+    fn unified_changes(&self, old: &Document, new: &Document) -> Vec<Change> {
+        let old_lines = old.as_vec_ref();
+        let new_lines = new.as_vec_ref();
+        let old_last = old_lines.len().wrapping_sub(1);
+        let new_last = new_lines.len().wrapping_sub(1);
+        let mut commons = vec![];
+        let mut broken = false;
+        let mut old_line_num = 0;
+        let mut new_line_num = 0;
+        let tail = (old_lines.len(), 0, new_lines.len(), 0, vec![], vec![]);
+        for change in self.as_vec_ref().iter().chain(Some(&tail)) {
+            let mut len = change.0 - old_line_num;
+            if len > 0 {
+                // only the last pair of a common run may contain a last line
+                let (old_end, new_end) = (old_line_num + len - 1, new_line_num + len - 1);
+                let old_no_newline = old.no_newline_at_eof() && old_end == old_last;
+                let new_no_newline = new.no_newline_at_eof() && new_end == new_last;
+                if old_no_newline != new_no_newline {
+                    len -= 1;
+                    broken = true;
+                }
+            }
+            if len > 0 {
+                commons.push((old_line_num, new_line_num, len));
+            }
+            old_line_num = change.0 + change.1;
+            new_line_num = change.2 + change.3;
+        }
+        if broken {
+            diff::changes_from_commons(old_lines, new_lines, &commons)
+        } else {
+            self.as_vec_ref().clone()
+        }
+    }
+
+    /// Highlight the words or chars changed in each Change, see `highlight::change_spans`.
+    /// example:
+    /// ```ignore
+    /// for (change, spans) in patch.as_vec_ref().iter().zip(patch.spans(Granularity::Word)) {
+    ///     for (line, spans) in change.5.iter().zip(&spans.added) {
+    ///         let words: Vec<_> = spans.iter().filter(|s| s.changed).map(|s| &line[s.start..s.end]).collect();
+    ///         println!("{:?}", words);
+    ///     }
+    /// }
+    /// ```
+    pub fn spans(&self, granularity: Granularity) -> Vec<ChangeSpans> {
+        self.as_vec_ref()
+            .iter()
+            .map(|change| highlight::change_spans(change, granularity))
+            .collect()
+    }
+
+    /// Encode Patch to text, the format:
+    /// the first line is the header: `\0patch,version,eof`,
+    /// eof is `-` if the newline state at the end of document is not changed,
+    /// `1` if the new document has no newline at the end, `0` otherwise.
+    /// Then each change starts with a line: `\0old_line_num,removed_count,new_line_num,added_count`,
+    /// followed by the removed lines and the added lines.
+    /// example:
+    /// ```text
+    /// \0patch,1,-
+    /// \01,1,1,2
+    /// B
+    /// X
+    /// Y
+    /// ```
+    pub fn encode(&self) -> String {
+        let eof = match self.no_newline_at_eof() {
+            None => "-",
+            Some(true) => "1",
+            Some(false) => "0",
+        };
+        let mut content = format!("\0patch,{},{}\n", PATCH_VERSION, eof);
+        for change in self.as_vec_ref() {
+            content.push_str(&format!(
+                "\0{},{},{},{}\n",
+                change.0, change.1, change.2, change.3
+            ));
+            for line in change.4.iter().chain(&change.5) {
+                content.push_str(line);
+                content.push('\n');
+            }
+        }
+        content
+    }
+
+    /// Decode Patch from text, see `Patch::encode` for the format.
+    pub fn decode(content: &str) -> Result<Self> {
+        let invalid = |msg: String| Error::new(ErrorKind::InvalidData, msg);
+        let mut lines = content.split('\n');
+        if lines.next_back() != Some("") {
+            return Err(invalid(s!("patch should end with newline")));
+        }
+
+        let header = lines.next().unwrap_or_default();
+        let fields: Vec<_> = header
+            .strip_prefix("\0")
+            .unwrap_or_default()
+            .split(',')
+            .collect();
+        if fields.len() != 3 || fields[0] != "patch" {
+            return Err(invalid(format!("invalid patch header: {:?}", header)));
+        }
+        if fields[1] != PATCH_VERSION.to_string() {
+            return Err(invalid(format!("unsupported patch version: {}", fields[1])));
+        }
+        let no_newline_at_eof = match fields[2] {
+            "-" => None,
+            "1" => Some(true),
+            "0" => Some(false),
+            eof => return Err(invalid(format!("invalid eof state: {}", eof))),
+        };
+
+        let mut changes = vec![];
+        while let Some(line) = lines.next() {
+            let nums = line
+                .strip_prefix("\0")
+                .and_then(|nums| {
+                    nums.split(',')
+                        .map(str::parse::<usize>)
+                        .collect::<std::result::Result<Vec<_>, _>>()
+                        .ok()
+                })
+                .unwrap_or_default();
+            if nums.len() != 4 {
+                return Err(invalid(format!("invalid change header: {:?}", line)));
+            }
+            let mut take = |count| {
+                let taken: Vec<String> = lines.by_ref().take(count).map(String::from).collect();
+                if taken.len() == count {
+                    Ok(taken)
+                } else {
+                    Err(invalid(format!("change {:?} is truncated", line)))
+                }
+            };
+            let removed_lines = take(nums[1])?;
+            let added_lines = take(nums[3])?;
+            changes.push((
+                nums[0],
+                nums[1],
+                nums[2],
+                nums[3],
+                removed_lines,
+                added_lines,
+            ));
+        }
+        Ok(Self::from_vec(changes).with_no_newline_at_eof(no_newline_at_eof))
+    }
+
+    /// Write Patch object to file system, return the hash.
     /// Step 1: calculate content sha-256 hash
     /// Step 2: use first two hex code as folder, the rest as file name.
     /// This idea comes from git.
-    pub fn write_to_store(&self) {
-        todo!()
+    pub fn write_to_store(&self, store: &ObjectStore) -> Result<String> {
+        store.write(self.encode().as_bytes())
     }
 
     /// Create a Patch object from file system.
-    pub fn read_from_store(hash: String) -> Self {
-        todo!()
+    pub fn read_from_store(store: &ObjectStore, hash: String) -> Result<Self> {
+        let content = store.read(&hash)?;
+        let content =
+            String::from_utf8(content).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        Self::decode(&content)
     }
 }
 
+/// Parse a hunk header `@@ -a[,b] +c[,d] @@`, return the 0-based line numbers and counts.
+/// The start of an empty range is the line before it.
+fn parse_hunk_header(header: &str) -> Option<(usize, usize, usize, usize)> {
+    let ranges = header.strip_prefix("@@ -")?;
+    let (ranges, _) = ranges.split_once(" @@")?;
+    let (old, new) = ranges.split_once(" +")?;
+    let range = |range: &str| -> Option<(usize, usize)> {
+        let (start, count): (usize, usize) = match range.split_once(',') {
+            Some((start, count)) => (start.parse().ok()?, count.parse().ok()?),
+            None => (range.parse().ok()?, 1),
+        };
+        // the end of range must not overflow on a hostile header
+        start.checked_add(count)?;
+        match (start, count) {
+            (start, 0) => Some((start, 0)),
+            (0, _) => None,
+            (start, count) => Some((start - 1, count)),
+        }
+    };
+    let (old_start, old_count) = range(old)?;
+    let (new_start, new_count) = range(new)?;
+    Some((old_start, old_count, new_start, new_count))
+}
+
 impl Add<Document> for Patch {
     type Output = Document;
     /// You can read Add trait for Document.
@@ -70,10 +644,309 @@ impl Add<Document> for Patch {
     }
 }
 
+impl Add<Patch> for Patch {
+    type Output = Patch;
+    /// Compose two Patches, see `Patch::compose`.
+    /// v0 + p1 + p2 = v0 + (p1 + p2)
+    fn add(self, next: Patch) -> Patch {
+        self.compose(&next)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use self::super::*;
     #[test]
     // #[ignore]
     fn test_from_fs() {}
+
+    #[test]
+    // #[ignore]
+    fn test_conflicts_with() {
+        let base = Document::from_content("A\nB\nC\nD\nE\nF\nG\n");
+        let main = Document::from_content("A\nX\nC\nD\nE\nY\nG\n");
+        let other = Document::from_content("A\nB\nZ\nC\nD\nE\nF\n");
+        let patch_main = main - base.clone();
+        let patch_other = other - base;
+
+        let conflicts = patch_other.conflicts_with(&patch_main);
+        assert_eq!(
+            vec![
+                // insert Z right after B which is changed to X
+                Conflict {
+                    ours: (2, 0, 2, 1, vec![], vec![s!("Z")]),
+                    theirs: (1, 1, 1, 1, vec![s!("B")], vec![s!("X")]),
+                },
+                // remove G right after F which is changed to Y
+                Conflict {
+                    ours: (6, 1, 7, 0, vec![s!("G")], vec![]),
+                    theirs: (5, 1, 5, 1, vec![s!("F")], vec![s!("Y")]),
+                },
+            ],
+            conflicts
+        );
+        // the conflict is symmetric
+        let conflicts = patch_main.conflicts_with(&patch_other);
+        assert_eq!(2, conflicts.len());
+        assert_eq!(vec![s!("X")], conflicts[0].ours.5);
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_rebase_onto() {
+        let release = Document::from_content("A\nB\nC\nD\nE\nF\nG\n");
+        let main = Document::from_content("X\nY\nA\nB\nC\nE\nF\nG\n");
+        let suggestion = Document::from_content("A\nB\nC\nD\nE\nZ\nG");
+        let upstream = main.clone() - release.clone();
+        let patch = suggestion - release.clone();
+
+        let rebased = patch.rebase_onto(&upstream).unwrap();
+        assert_eq!(
+            vec![(6, 1, 6, 1, vec![s!("F")], vec![s!("Z")])],
+            *rebased.as_vec_ref()
+        );
+        assert_eq!(Some(true), rebased.no_newline_at_eof());
+        assert_eq!(
+            Document::from_content("X\nY\nA\nB\nC\nE\nZ\nG"),
+            main + rebased
+        );
+
+        // C is changed next to the removed D
+        let patch = Document::from_content("A\nB\nW\nD\nE\nF\nG\n") - release;
+        let conflict = patch.rebase_onto(&upstream).unwrap_err();
+        assert_eq!(vec![s!("W")], conflict.ours.5);
+        assert_eq!(vec![s!("D")], conflict.theirs.4);
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_invert() {
+        let old = Document::from_content("A\nB\nC\nD");
+        let new = Document::from_content("X\nB\nD\nE\n");
+        let patch = new.clone() - old.clone();
+        assert_eq!(old - new.clone(), patch.invert());
+        assert_eq!(patch, patch.invert().invert());
+        // undo
+        assert_eq!(Patch::from_vec(vec![]), patch.clone() + patch.invert());
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_compose() {
+        let v0 = Document::from_content("A\nB\nC\nD\nE\nF\n");
+        let v1 = Document::from_content("A\nX\nY\nC\nD\nE\nF\n");
+        let v2 = Document::from_content("A\nX\nZ\nC\nD\nF");
+        let p1 = v1.clone() - v0.clone();
+        let p2 = v2.clone() - v1;
+
+        let patch = p1.compose(&p2);
+        assert_eq!(
+            vec![
+                (1, 1, 1, 2, vec![s!("B")], vec![s!("X"), s!("Z")]),
+                (4, 1, 5, 0, vec![s!("E")], vec![]),
+            ],
+            *patch.as_vec_ref()
+        );
+        assert_eq!(Some(true), patch.no_newline_at_eof());
+        assert_eq!(v2, v0.clone() + (p1.clone() + p2.clone()));
+
+        // p2 undoes p1
+        let undo = v0.clone() - v2;
+        assert_eq!(Patch::from_vec(vec![]), (p1 + p2) + undo);
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_to_unified() {
+        // generated by `diff -u [-U<n>] --label a --label b tests/<old> tests/<new>`
+        for entry in fs::read_dir("tests/unified").unwrap() {
+            let path = entry.unwrap().path();
+            let name = path.file_stem().unwrap().to_string_lossy().to_string();
+            let (name, context_lines) = match name.split_once(".U") {
+                Some((name, n)) => (s!(name), n.parse().unwrap()),
+                None => (name, 3),
+            };
+            let (old, new) = name.split_once('-').unwrap();
+            let old = Document::from_fs(format!("tests/{}", old));
+            let new = Document::from_fs(format!("tests/{}", new));
+            let patch = new.clone() - old.clone();
+            assert_eq!(
+                fs::read_to_string(&path).unwrap(),
+                patch.to_unified(&old, &new, context_lines),
+                "{:?}",
+                path
+            );
+        }
+
+        let old = Document::from_content("A\nB\nC\nD\nE\nF\nG\nH\nI\nJ\n");
+        let new = Document::from_content("A\nX\nC\nD\nE\nF\nG\nH\nI\n");
+        let patch = new.clone() - old.clone();
+        assert_eq!(
+            "--- a\n+++ b\n@@ -1,3 +1,3 @@\n A\n-B\n+X\n C\n@@ -9,2 +9 @@\n I\n-J\n",
+            patch.to_unified(&old, &new, 1)
+        );
+        assert_eq!("", Patch::from_vec(vec![]).to_unified(&old, &old, 3));
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_from_unified() {
+        for entry in fs::read_dir("tests/unified").unwrap() {
+            let path = entry.unwrap().path();
+            let name = path.file_stem().unwrap().to_string_lossy().to_string();
+            let name = name.split('.').next().unwrap();
+            let (old, new) = name.split_once('-').unwrap();
+            let old = Document::from_fs(format!("tests/{}", old));
+            let new = Document::from_fs(format!("tests/{}", new));
+            let patch = Patch::from_unified(&fs::read_to_string(&path).unwrap()).unwrap();
+            assert_eq!(Ok(new), old.apply(&patch), "{:?}", path);
+        }
+
+        let old = Document::from_content("A\r\nB\r\n");
+        let new = Document::from_content("A\r\nX\r\n");
+        let patch = Patch::from_unified(&(new.clone() - old.clone()).to_unified(&old, &new, 3));
+        assert_eq!(Ok(new), old.apply(&patch.unwrap()));
+
+        // git diff with multiple hunks
+        let content = "diff --git a/ch01.md b/ch01.md\n\
+            index 0123456..789abcd 100644\n\
+            --- a/ch01.md\n\
+            +++ b/ch01.md\n\
+            @@ -1,3 +1,3 @@ # Title\n \
+            A\n-B\n+X\n C\n\
+            @@ -8,2 +8,3 @@\n H\n+Y\n I\n";
+        let patch = Patch::from_unified(content).unwrap();
+        assert_eq!(
+            vec![
+                (1, 1, 1, 1, vec![s!("B")], vec![s!("X")]),
+                (8, 0, 8, 1, vec![], vec![s!("Y")]),
+            ],
+            *patch.as_vec_ref()
+        );
+
+        for content in &[
+            "@@ -1,2 +1,2 @@\n A\n-B\n",
+            "@@ -1,2 +1,2 @@\n A\n-B\n+X\n C\n",
+            "@@ -1 +1 @@\n*A\n",
+            "@@ -1,2 +1 @@\n-A\n-B\n@@ -0,0 +1 @@\n+C\n",
+            "@@ -1 +2 @@\n-A\n+B\n",
+            "@@ -1,x +1 @@\n A\n",
+            "@@ -2,18446744073709551615 +2 @@\n A\n",
+            "@@ -1 +1 @@\n-A\n+B\nC\n",
+            "@@ -1 +1 @@\n中\n",
+        ] {
+            let err = Patch::from_unified(content).unwrap_err();
+            assert_eq!(ErrorKind::InvalidData, err.kind(), "{:?}", content);
+        }
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_touches() {
+        let change = |start, removed| (start, removed, 0, 0, vec![], vec![]);
+        assert!(touches(&change(1, 2), &change(2, 2)));
+        assert!(touches(&change(1, 2), &change(3, 0)));
+        assert!(touches(&change(1, 2), &change(0, 1)));
+        assert!(touches(&change(3, 0), &change(3, 0)));
+        assert!(!touches(&change(1, 2), &change(4, 0)));
+        assert!(!touches(&change(1, 0), &change(2, 1)));
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_encode() {
+        let base = Document::from_fs(s!("tests/base"));
+        let new = Document::from_fs(s!("tests/change_one"));
+        let patch = new - base;
+        assert_eq!(s!("\0patch,1,-\n\x002,1,2,2\nC\nX\nY\n"), patch.encode());
+        assert_eq!(patch, Patch::decode(&patch.encode()).unwrap());
+
+        let patch = Patch::from_vec(vec![(0, 1, 0, 0, vec![s!("\0A\r")], vec![])])
+            .with_no_newline_at_eof(Some(true));
+        assert_eq!(patch, Patch::decode(&patch.encode()).unwrap());
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_decode_invalid() {
+        for content in &[
+            "",
+            "\0patch,1,-",
+            "\0patch,2,-\n",
+            "\0patch,1,x\n",
+            "\0patch,1,-\nA\n",
+            "\0patch,1,-\n\x000,1,0\nA\n",
+            "\0patch,1,-\n\x000,2,0,0\nA\n",
+            "\0patch,1,-\n\x000,x,0,0,0\n",
+        ] {
+            let err = Patch::decode(content).unwrap_err();
+            assert_eq!(ErrorKind::InvalidData, err.kind());
+        }
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ObjectStore::new(dir.path());
+        let base = Document::from_fs(s!("tests/base"));
+        let new = Document::from_fs(s!("tests/complex"));
+        let patch = new.clone() - base.clone();
+
+        let base_hash = base.write_to_store(&store).unwrap();
+        let patch_hash = patch.write_to_store(&store).unwrap();
+        let base = Document::read_from_store(&store, base_hash).unwrap();
+        let patch = Patch::read_from_store(&store, patch_hash).unwrap();
+        assert_eq!(new, base + patch);
+    }
+
+    proptest::proptest! {
+        /// old + Patch::from_unified(patch.to_unified(old, new)) == new
+        #[test]
+        fn prop_unified(
+            old in "[a-e\n]{0,80}",
+            new in "[a-e\n]{0,80}",
+            context_lines in 0usize..4,
+        ) {
+            let old = Document::from_content(&old);
+            let new = Document::from_content(&new);
+            let patch = new.clone() - old.clone();
+            let unified = patch.to_unified(&old, &new, context_lines);
+            let parsed = Patch::from_unified(&unified).unwrap();
+            proptest::prop_assert_eq!(Ok(new), old.apply(&parsed));
+        }
+
+        /// v0 + p1.compose(p2) == v0 + p1 + p2
+        #[test]
+        fn prop_compose(
+            v0 in "[a-e\n]{0,60}",
+            v1 in "[a-e\n]{0,60}",
+            v2 in "[a-e\n]{0,60}",
+        ) {
+            let v0 = Document::from_content(&v0);
+            let v1 = Document::from_content(&v1);
+            let v2 = Document::from_content(&v2);
+            let p1 = v1.clone() - v0.clone();
+            let p2 = v2.clone() - v1;
+            proptest::prop_assert_eq!(v2, v0 + p1.compose(&p2));
+        }
+
+        /// base + upstream + patch.rebase_onto(upstream) == merge3(base, upstream, patch)
+        #[test]
+        fn prop_rebase_onto(
+            base in "[a-e\n]{0,60}",
+            main in "[a-e\n]{0,60}",
+            other in "[a-e\n]{0,60}",
+        ) {
+            let base = Document::from_content(&base);
+            let main = Document::from_content(&main);
+            let other = Document::from_content(&other);
+            let upstream = main.clone() - base.clone();
+            let patch = other.clone() - base.clone();
+            if let Ok(rebased) = patch.rebase_onto(&upstream) {
+                let merged = Document::merge3(&base, &main, &other).document();
+                proptest::prop_assert_eq!(merged, Some(main + rebased));
+            }
+        }
+    }
 }
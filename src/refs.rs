@@ -0,0 +1,361 @@
+//! This mod implements the refs, a ref is a named pointer to a Commit hash.
+//! The `main` ref is the Head of mainline, it's maintained by the maintainer.
+//! Contributors create side branches under `contributors/<contributor>/`.
+//!
+//! Each ref is a file under the root directory, containing the Commit hash.
+//! A ref is updated by compare-and-swap: the writer tells the hash it expects,
+//! the update fails if another writer has moved the ref.
+//! Like git, the new hash is written to a `<ref>.lock` file created exclusively,
+//! then the lock file is renamed to the ref file.
+
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use crate::macros::s;
+
+/// The Head of mainline.
+pub const MAIN: &str = "main";
+
+/// The prefix of contributor branches.
+pub const CONTRIBUTORS: &str = "contributors";
+
+#[derive(Debug)]
+pub enum RefError {
+    Io(io::Error),
+    /// The ref name is not valid.
+    InvalidName(String),
+    /// The user is not allowed to update the ref.
+    PermissionDenied {
+        name: String,
+        user: String,
+    },
+    /// Another writer is updating the ref.
+    Locked(String),
+    /// The ref is not at the expected hash.
+    Conflict {
+        name: String,
+        expected: Option<String>,
+        actual: Option<String>,
+    },
+}
+
+impl fmt::Display for RefError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RefError::Io(e) => write!(f, "{}", e),
+            RefError::InvalidName(name) => write!(f, "invalid ref name: {:?}", name),
+            RefError::PermissionDenied { name, user } => {
+                write!(f, "{} is not allowed to update ref {}", user, name)
+            }
+            RefError::Locked(name) => write!(f, "ref {} is locked by another writer", name),
+            RefError::Conflict {
+                name,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "ref {} is at {:?}, expected {:?}",
+                name, actual, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RefError {}
+
+impl From<io::Error> for RefError {
+    fn from(e: io::Error) -> Self {
+        RefError::Io(e)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, RefError>;
+
+/// The refs of a document.
+/// example:
+/// ```ignore
+/// let refs = RefStore::new("refs/chapter1", s!("eq99"));
+/// refs.update(MAIN, None, &commit_hash, "eq99")?;
+/// let branch = RefStore::branch_name("alice", "typo");
+/// refs.update(&branch, None, &main_hash, "alice")?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct RefStore {
+    root: PathBuf,
+    maintainer: String,
+}
+
+impl RefStore {
+    /// Create a RefStore rooted at `root`, only the `maintainer` can update `main`.
+    pub fn new<P: Into<PathBuf>>(root: P, maintainer: String) -> Self {
+        Self {
+            root: root.into(),
+            maintainer,
+        }
+    }
+
+    pub fn maintainer(&self) -> &str {
+        &self.maintainer
+    }
+
+    /// The name of a contributor branch: `contributors/<contributor>/<branch>`.
+    pub fn branch_name(contributor: &str, branch: &str) -> String {
+        format!("{}/{}/{}", CONTRIBUTORS, contributor, branch)
+    }
+
+    /// Read the Commit hash of a ref, None if the ref doesn't exist.
+    pub fn read(&self, name: &str) -> Result<Option<String>> {
+        let path = self.ref_path(name)?;
+        match fs::read_to_string(path) {
+            Ok(content) => Ok(Some(s!(content.trim_end()))),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Point a ref to a new Commit hash if the ref is at `expected`,
+    /// `expected` is None if the ref should not exist yet.
+    pub fn update(&self, name: &str, expected: Option<&str>, new: &str, user: &str) -> Result<()> {
+        self.compare_and_swap(name, expected, Some(new), user)
+    }
+
+    /// Delete a ref if the ref is at `expected`.
+    pub fn delete(&self, name: &str, expected: &str, user: &str) -> Result<()> {
+        self.compare_and_swap(name, Some(expected), None, user)
+    }
+
+    /// List all refs and their Commit hashes, sorted by name.
+    pub fn list(&self) -> Result<Vec<(String, String)>> {
+        let mut refs = vec![];
+        if self.root.exists() {
+            self.list_dir(&self.root, &mut refs)?;
+        }
+        refs.sort();
+        Ok(refs)
+    }
+
+    /// List the refs under `<prefix>/` like `releases`, only this directory is read.
+    pub fn list_prefix(&self, prefix: &str) -> Result<Vec<(String, String)>> {
+        let dir = self.ref_path(prefix)?;
+        let mut refs = vec![];
+        if dir.is_dir() {
+            self.list_dir(&dir, &mut refs)?;
+        }
+        refs.sort();
+        Ok(refs)
+    }
+
+    fn list_dir(&self, dir: &Path, refs: &mut Vec<(String, String)>) -> Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                self.list_dir(&path, refs)?;
+            } else if path.extension().is_none_or(|ext| ext != "lock") {
+                let name = path
+                    .strip_prefix(&self.root)
+                    .unwrap()
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/");
+                if let Some(hash) = self.read(&name)? {
+                    refs.push((name, hash));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Check whether the user can update a ref:
+    /// the maintainer can update any ref,
+    /// a contributor can only update the refs under `contributors/<contributor>/`.
+    pub fn can_update(&self, name: &str, user: &str) -> bool {
+        user == self.maintainer
+            || name
+                .strip_prefix(CONTRIBUTORS)
+                .and_then(|rest| rest.strip_prefix('/'))
+                .and_then(|rest| rest.strip_prefix(user))
+                .is_some_and(|rest| rest.starts_with('/'))
+    }
+
+    fn compare_and_swap(
+        &self,
+        name: &str,
+        expected: Option<&str>,
+        new: Option<&str>,
+        user: &str,
+    ) -> Result<()> {
+        let path = self.ref_path(name)?;
+        if !self.can_update(name, user) {
+            return Err(RefError::PermissionDenied {
+                name: s!(name),
+                user: s!(user),
+            });
+        }
+
+        // step 1: take the lock
+        fs::create_dir_all(path.parent().unwrap())?;
+        let lock_path = path.with_file_name(format!(
+            "{}.lock",
+            path.file_name().unwrap().to_string_lossy()
+        ));
+        let mut lock = match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&lock_path)
+        {
+            Ok(lock) => lock,
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                return Err(RefError::Locked(s!(name)))
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        // step 2: compare and swap, the lock is released unless it's renamed to the ref,
+        // after the rename the lock path may be taken by another writer already
+        let mut renamed = false;
+        let result = (|| {
+            let actual = self.read(name)?;
+            if actual.as_deref() != expected {
+                return Err(RefError::Conflict {
+                    name: s!(name),
+                    expected: expected.map(String::from),
+                    actual,
+                });
+            }
+            match new {
+                Some(hash) => {
+                    writeln!(lock, "{}", hash)?;
+                    lock.sync_all()?;
+                    fs::rename(&lock_path, &path)?;
+                    renamed = true;
+                }
+                None => fs::remove_file(&path)?,
+            }
+            Ok(())
+        })();
+        if !renamed {
+            // the error of compare and swap is more useful than the error of cleanup
+            let _ = fs::remove_file(&lock_path);
+        }
+        result
+    }
+
+    /// Where the ref is saved, the name is checked:
+    /// it consists of segments separated by `/`,
+    /// each segment is made of letters, digits, `-`, `_` and `.`,
+    /// it doesn't start with `.` or end with `.lock`.
+    fn ref_path(&self, name: &str) -> Result<PathBuf> {
+        let valid = name.split('/').all(|segment| {
+            !segment.is_empty()
+                && !segment.starts_with('.')
+                && !segment.ends_with(".lock")
+                && segment
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+        });
+        if !valid {
+            return Err(RefError::InvalidName(s!(name)));
+        }
+        Ok(name
+            .split('/')
+            .fold(self.root.clone(), |path, s| path.join(s)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use self::super::*;
+
+    #[test]
+    // #[ignore]
+    fn test_update() {
+        let dir = tempfile::tempdir().unwrap();
+        let refs = RefStore::new(dir.path(), s!("eq99"));
+        assert_eq!(None, refs.read(MAIN).unwrap());
+
+        refs.update(MAIN, None, "c1", "eq99").unwrap();
+        assert_eq!(Some(s!("c1")), refs.read(MAIN).unwrap());
+        refs.update(MAIN, Some("c1"), "c2", "eq99").unwrap();
+        assert_eq!(Some(s!("c2")), refs.read(MAIN).unwrap());
+
+        // the second writer expects the old hash
+        match refs.update(MAIN, Some("c1"), "c3", "eq99") {
+            Err(RefError::Conflict {
+                expected, actual, ..
+            }) => {
+                assert_eq!(Some(s!("c1")), expected);
+                assert_eq!(Some(s!("c2")), actual);
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        // create an existing ref
+        assert!(matches!(
+            refs.update(MAIN, None, "c3", "eq99"),
+            Err(RefError::Conflict { .. })
+        ));
+        assert_eq!(Some(s!("c2")), refs.read(MAIN).unwrap());
+        // the lock is released after a failed update
+        assert!(!dir.path().join("main.lock").exists());
+
+        refs.delete(MAIN, "c2", "eq99").unwrap();
+        assert_eq!(None, refs.read(MAIN).unwrap());
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_permission() {
+        let dir = tempfile::tempdir().unwrap();
+        let refs = RefStore::new(dir.path(), s!("eq99"));
+        assert!(matches!(
+            refs.update(MAIN, None, "c1", "alice"),
+            Err(RefError::PermissionDenied { .. })
+        ));
+
+        let alice = RefStore::branch_name("alice", "typo");
+        let alicia = RefStore::branch_name("alicia", "typo");
+        refs.update(&alice, None, "c1", "alice").unwrap();
+        refs.update(&alicia, None, "c1", "eq99").unwrap();
+        assert!(matches!(
+            refs.update(&alicia, Some("c1"), "c2", "alice"),
+            Err(RefError::PermissionDenied { .. })
+        ));
+
+        assert_eq!(
+            vec![(alice.clone(), s!("c1")), (alicia, s!("c1"))],
+            refs.list().unwrap()
+        );
+        assert_eq!(
+            vec![(alice, s!("c1"))],
+            refs.list_prefix("contributors/alice").unwrap()
+        );
+        assert!(refs.list_prefix("releases").unwrap().is_empty());
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        let refs = RefStore::new(dir.path(), s!("eq99"));
+        fs::write(dir.path().join("main.lock"), "").unwrap();
+        assert!(matches!(
+            refs.update(MAIN, None, "c1", "eq99"),
+            Err(RefError::Locked(_))
+        ));
+        // the lock of another writer is not released
+        assert!(dir.path().join("main.lock").exists());
+        assert!(refs.list().unwrap().is_empty());
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_invalid_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let refs = RefStore::new(dir.path(), s!("eq99"));
+        for name in &["", "../main", "a//b", ".hidden", "main.lock", "a b"] {
+            assert!(matches!(refs.read(name), Err(RefError::InvalidName(_))));
+        }
+    }
+}
@@ -0,0 +1,447 @@
+//! This mod renders a Patch against its base Document for reviewers.
+//! The Changes are grouped into hunks with their context lines like a unified diff,
+//! each hunk is a list of rows: a common line, or a Change.
+//! The terminal renderer shows the hunks inline or side by side, with ANSI colors.
+
+use crate::document::{ApplyError, Document};
+use crate::patch::{Change, Patch};
+
+/// A row of a hunk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Row<'a> {
+    /// a context line, with its line numbers in old and new documents
+    Common {
+        old_line_num: usize,
+        new_line_num: usize,
+        line: &'a str,
+    },
+    Change(&'a Change),
+}
+
+/// The Changes close to each other and their context lines.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenderHunk<'a> {
+    /// line range [start, end) in old document
+    pub old_range: (usize, usize),
+    /// line range [start, end) in new document
+    pub new_range: (usize, usize),
+    pub rows: Vec<Row<'a>>,
+}
+
+impl RenderHunk<'_> {
+    /// The hunk header like a unified diff: `@@ -a,b +c,d @@`.
+    pub fn header(&self) -> String {
+        let range = |(start, end): (usize, usize)| match end - start {
+            0 => format!("{},0", start),
+            1 => format!("{}", start + 1),
+            len => format!("{},{}", start + 1, len),
+        };
+        format!(
+            "@@ -{} +{} @@",
+            range(self.old_range),
+            range(self.new_range)
+        )
+    }
+}
+
+/// Group the Changes of patch into hunks with up to `context_lines` lines of old around them,
+/// Changes whose gaps are covered by the context lines are in the same hunk.
+/// The patch is checked against old first, see `Document::apply`.
+pub fn hunks<'a>(
+    old: &'a Document,
+    patch: &'a Patch,
+    context_lines: usize,
+) -> Result<Vec<RenderHunk<'a>>, ApplyError> {
+    old.apply(patch)?;
+    Ok(group(old, patch, context_lines))
+}
+
+/// See `hunks`, the patch must apply to old.
+pub(crate) fn group<'a>(
+    old: &'a Document,
+    patch: &'a Patch,
+    context_lines: usize,
+) -> Vec<RenderHunk<'a>> {
+    let old_lines = old.as_vec_ref();
+    let changes = patch.as_vec_ref();
+    let mut hunks = vec![];
+    let mut idx = 0;
+    while idx < changes.len() {
+        let mut last = idx;
+        while last + 1 < changes.len()
+            && changes[last + 1].0 - (changes[last].0 + changes[last].1) <= 2 * context_lines
+        {
+            last += 1;
+        }
+        let (first_change, last_change) = (&changes[idx], &changes[last]);
+        let old_start = first_change.0.saturating_sub(context_lines);
+        let old_end = (last_change.0 + last_change.1 + context_lines).min(old_lines.len());
+        let new_start = first_change.2 - (first_change.0 - old_start);
+        let new_end = last_change.2 + last_change.3 + (old_end - last_change.0 - last_change.1);
+
+        let mut rows = vec![];
+        let common = |rows: &mut Vec<Row<'a>>, start: usize, end: usize, offset: isize| {
+            for (old_line_num, line) in (start..end).zip(&old_lines[start..end]) {
+                rows.push(Row::Common {
+                    old_line_num,
+                    new_line_num: (old_line_num as isize + offset) as usize,
+                    line,
+                });
+            }
+        };
+        let mut old_line_num = old_start;
+        let mut offset = new_start as isize - old_start as isize;
+        for change in &changes[idx..=last] {
+            common(&mut rows, old_line_num, change.0, offset);
+            rows.push(Row::Change(change));
+            old_line_num = change.0 + change.1;
+            offset += change.3 as isize - change.1 as isize;
+        }
+        common(&mut rows, old_line_num, old_end, offset);
+
+        hunks.push(RenderHunk {
+            old_range: (old_start, old_end),
+            new_range: (new_start, new_end),
+            rows,
+        });
+        idx = last + 1;
+    }
+    hunks
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    /// `-` and `+` lines one after another, like `diff -u`
+    Inline,
+    /// old document on the left, new document on the right
+    SideBySide,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TerminalOptions {
+    pub layout: Layout,
+    /// false to render without ANSI colors, e.g. when the output is not a terminal
+    pub color: bool,
+    /// the width of terminal in columns, longer lines are truncated
+    pub width: usize,
+    pub context_lines: usize,
+}
+
+impl Default for TerminalOptions {
+    fn default() -> Self {
+        Self {
+            layout: Layout::Inline,
+            color: true,
+            width: 80,
+            context_lines: 3,
+        }
+    }
+}
+
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const CYAN: &str = "\x1b[36m";
+const RESET: &str = "\x1b[0m";
+
+/// Render patch against old for a terminal.
+/// example:
+/// ```ignore
+/// let options = TerminalOptions {
+///     layout: Layout::SideBySide,
+///     width: 120,
+///     ..TerminalOptions::default()
+/// };
+/// print!("{}", render_terminal(&old, &(new - old.clone()), options)?);
+/// ```
+pub fn render_terminal(
+    old: &Document,
+    patch: &Patch,
+    options: TerminalOptions,
+) -> Result<String, ApplyError> {
+    let hunks = hunks(old, patch, options.context_lines)?;
+    // the width of line numbers
+    let num_width = hunks
+        .iter()
+        .map(|hunk| hunk.old_range.1.max(hunk.new_range.1))
+        .max()
+        .unwrap_or_default()
+        .to_string()
+        .len();
+    let paint = |text: String, color: &str| {
+        if options.color {
+            format!("{}{}{}", color, text, RESET)
+        } else {
+            text
+        }
+    };
+    let mut content = String::new();
+    for hunk in &hunks {
+        content.push_str(&paint(hunk.header(), CYAN));
+        content.push('\n');
+        match options.layout {
+            Layout::Inline => {
+                // `old new sign line`
+                let text_width = options.width.saturating_sub(2 * num_width + 3);
+                let cell = |old: Option<usize>, new: Option<usize>, sign: char, line: &str| {
+                    let num = |n: Option<usize>| match n {
+                        Some(n) => format!("{:>1$}", n + 1, num_width),
+                        None => " ".repeat(num_width),
+                    };
+                    let (text, _) = fit(line, text_width);
+                    format!("{} {} {}{}", num(old), num(new), sign, text)
+                };
+                for row in &hunk.rows {
+                    match row {
+                        Row::Common {
+                            old_line_num,
+                            new_line_num,
+                            line,
+                        } => {
+                            content.push_str(&cell(
+                                Some(*old_line_num),
+                                Some(*new_line_num),
+                                ' ',
+                                line,
+                            ));
+                            content.push('\n');
+                        }
+                        Row::Change(change) => {
+                            for (i, line) in change.4.iter().enumerate() {
+                                content.push_str(&paint(
+                                    cell(Some(change.0 + i), None, '-', line),
+                                    RED,
+                                ));
+                                content.push('\n');
+                            }
+                            for (i, line) in change.5.iter().enumerate() {
+                                content.push_str(&paint(
+                                    cell(None, Some(change.2 + i), '+', line),
+                                    GREEN,
+                                ));
+                                content.push('\n');
+                            }
+                        }
+                    }
+                }
+            }
+            Layout::SideBySide => {
+                // `old sign line │ new sign line`, both sides have the same width
+                let side_width = options.width.saturating_sub(3) / 2;
+                let text_width = side_width.saturating_sub(num_width + 2);
+                let cell = |side: Option<(usize, char, &str)>| match side {
+                    Some((line_num, sign, line)) => {
+                        let (text, width) = fit(line, text_width);
+                        let padding = " ".repeat(text_width - width);
+                        format!(
+                            "{:>width$} {}{}{}",
+                            line_num + 1,
+                            sign,
+                            text,
+                            padding,
+                            width = num_width
+                        )
+                    }
+                    None => " ".repeat(side_width),
+                };
+                for row in &hunk.rows {
+                    match row {
+                        Row::Common {
+                            old_line_num,
+                            new_line_num,
+                            line,
+                        } => {
+                            let left = cell(Some((*old_line_num, ' ', line)));
+                            let right = cell(Some((*new_line_num, ' ', line)));
+                            content.push_str(format!("{} │ {}", left, right).trim_end());
+                            content.push('\n');
+                        }
+                        Row::Change(change) => {
+                            for i in 0..change.1.max(change.3) {
+                                let left = change
+                                    .4
+                                    .get(i)
+                                    .map(|line| paint(cell(Some((change.0 + i, '-', line))), RED));
+                                let right = change.5.get(i).map(|line| {
+                                    paint(cell(Some((change.2 + i, '+', line))), GREEN)
+                                });
+                                let row = format!(
+                                    "{} │ {}",
+                                    left.unwrap_or_else(|| cell(None)),
+                                    right.unwrap_or_default()
+                                );
+                                content.push_str(row.trim_end());
+                                content.push('\n');
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+    Ok(content)
+}
+
+/// The columns a char takes in terminal, East Asian wide chars take 2 columns.
+fn char_width(c: char) -> usize {
+    match c as u32 {
+        0x1100..=0x115F
+        | 0x2E80..=0x303E
+        | 0x3041..=0x33FF
+        | 0x3400..=0x4DBF
+        | 0x4E00..=0x9FFF
+        | 0xA000..=0xA4CF
+        | 0xAC00..=0xD7A3
+        | 0xF900..=0xFAFF
+        | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6
+        | 0x20000..=0x3FFFD => 2,
+        _ => 1,
+    }
+}
+
+/// Fit a line in width columns, a longer line is truncated and ends with `…`.
+/// Tabs are expanded and `\r` is dropped, they break the columns.
+/// The other control chars like ESC are shown as `\u{1b}`,
+/// a contributor's line must not drive the terminal.
+/// Return the text and its width.
+fn fit(line: &str, width: usize) -> (String, usize) {
+    let mut cleaned = String::with_capacity(line.len());
+    for c in line.chars() {
+        match c {
+            '\t' => cleaned.push_str("    "),
+            '\r' => {}
+            c if c.is_control() => cleaned.extend(c.escape_unicode()),
+            c => cleaned.push(c),
+        }
+    }
+    let line = cleaned;
+    let total: usize = line.chars().map(char_width).sum();
+    if total <= width {
+        return (line, total);
+    }
+    let mut text = String::new();
+    let mut text_width = 0;
+    for c in line.chars() {
+        if text_width + char_width(c) + 1 > width {
+            break;
+        }
+        text.push(c);
+        text_width += char_width(c);
+    }
+    if width > 0 {
+        text.push('…');
+        text_width += 1;
+    }
+    (text, text_width)
+}
+
+#[cfg(test)]
+mod tests {
+    use self::super::*;
+    use crate::macros::s;
+
+    #[test]
+    // #[ignore]
+    fn test_hunks() {
+        let old = Document::from_content("A\nB\nC\nD\nE\nF\nG\nH\nI\nJ\n");
+        let new = Document::from_content("A\nX\nC\nD\nE\nF\nG\nH\nI\n");
+        let patch = new - old.clone();
+        let hunks = hunks(&old, &patch, 1).unwrap();
+        assert_eq!(2, hunks.len());
+        assert_eq!("@@ -1,3 +1,3 @@", hunks[0].header());
+        assert_eq!("@@ -9,2 +9 @@", hunks[1].header());
+        assert_eq!(
+            vec![
+                Row::Common {
+                    old_line_num: 8,
+                    new_line_num: 8,
+                    line: "I"
+                },
+                Row::Change(&patch.as_vec_ref()[1]),
+            ],
+            hunks[1].rows
+        );
+
+        // the patch is not made from old
+        let other = Document::from_content("A\nB\n");
+        assert!(matches!(
+            super::hunks(&other, &patch, 1),
+            Err(ApplyError::OutOfRange { .. })
+        ));
+        assert!(render_terminal(&other, &patch, TerminalOptions::default()).is_err());
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_render_inline() {
+        let old = Document::from_fs(s!("tests/base"));
+        let new = Document::from_fs(s!("tests/change_one"));
+        let patch = new - old.clone();
+        let options = TerminalOptions {
+            color: false,
+            context_lines: 1,
+            ..TerminalOptions::default()
+        };
+        assert_eq!(
+            "@@ -2,3 +2,4 @@\n2 2  B\n3   -C\n  3 +X\n  4 +Y\n4 5  D\n",
+            render_terminal(&old, &patch, options).unwrap()
+        );
+
+        let options = TerminalOptions {
+            context_lines: 0,
+            ..TerminalOptions::default()
+        };
+        assert_eq!(
+            "\x1b[36m@@ -3 +3,2 @@\x1b[0m\n\
+             \x1b[31m3   -C\x1b[0m\n\
+             \x1b[32m  3 +X\x1b[0m\n\
+             \x1b[32m  4 +Y\x1b[0m\n",
+            render_terminal(&old, &patch, options).unwrap()
+        );
+
+        // the control chars of a line are escaped
+        let hostile = Document::from_content("A\n\x1b]0;pwned\x07\x1b[2J\n");
+        let patch = hostile - old.clone();
+        let rendered = render_terminal(&old, &patch, options).unwrap();
+        assert!(!rendered.contains('\x07'));
+        assert!(rendered.contains("+\\u{1b}]0;pwned\\u{7}\\u{1b}[2J\x1b[0m\n"));
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_render_side_by_side() {
+        let old = Document::from_content("A\nB\nC\n");
+        let new = Document::from_content("A\n修改一个很长的句子\nC\nD\n");
+        let patch = new - old.clone();
+        let options = TerminalOptions {
+            layout: Layout::SideBySide,
+            color: false,
+            width: 23,
+            context_lines: 3,
+        };
+        assert_eq!(
+            "@@ -1,3 +1,4 @@\n\
+             1  A       │ 1  A\n\
+             2 -B       │ 2 +修改一…\n\
+             3  C       │ 3  C\n\
+             \x20          │ 4 +D\n",
+            render_terminal(&old, &patch, options).unwrap()
+        );
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_fit() {
+        assert_eq!((s!("abc"), 3), fit("abc", 3));
+        assert_eq!((s!("ab…"), 3), fit("abcd", 3));
+        assert_eq!((s!("文…"), 3), fit("文档", 3));
+        assert_eq!((s!("…"), 1), fit("文档", 2));
+        assert_eq!((s!("    a"), 5), fit("\ta\r", 5));
+        assert_eq!(
+            (s!("\\u{1b}]0;pwned\\u{7}\\u{1b}[2J\\u{7f}"), 34),
+            fit("\x1b]0;pwned\x07\x1b[2J\x7f", 80)
+        );
+        assert_eq!((s!("\\u{9b}…"), 7), fit("\u{9b}2J", 7));
+    }
+}
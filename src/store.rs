@@ -0,0 +1,195 @@
+//! This mod implements the content-addressed object store.
+//! Every object is saved as a file named by the sha-256 hash of its content:
+//! the first two hex code is the folder, the rest is the file name.
+//! This idea comes from git.
+
+use std::fs;
+use std::io::{Error, ErrorKind, Result, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use sha2::{Digest, Sha256};
+
+/// The count of temporary files created by this process.
+static TMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// The ObjectStore saves objects under a root directory.
+/// example:
+/// ```ignore
+/// let store = ObjectStore::new("/var/lib/carbon/objects");
+/// let hash = store.write(b"A\nB\n")?;
+/// assert_eq!(b"A\nB\n".to_vec(), store.read(&hash)?);
+/// ```
+#[derive(Debug, Clone)]
+pub struct ObjectStore {
+    root: PathBuf,
+}
+
+impl ObjectStore {
+    /// Create an ObjectStore rooted at `root`,
+    /// the directory is created when the first object is written.
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        Self { root: root.into() }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Calculate the sha-256 hash of content, in lower case hex code.
+    pub fn hash(content: &[u8]) -> String {
+        Sha256::digest(content)
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    /// Write content to the store and return its hash.
+    /// Identical content is saved only once.
+    /// The content is written to a temporary file then renamed,
+    /// so a reader never sees a half written object.
+    pub fn write(&self, content: &[u8]) -> Result<String> {
+        let hash = Self::hash(content);
+        let path = self.object_path(&hash)?;
+        if path.exists() {
+            return Ok(hash);
+        }
+        let folder = path.parent().unwrap();
+        fs::create_dir_all(folder)?;
+        // the temporary file is unique to each call, even in the same process
+        let tmp_path = folder.join(format!(
+            "{}.tmp.{}.{}",
+            &hash[2..],
+            std::process::id(),
+            TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let result = (|| {
+            let mut tmp_file = fs::File::create(&tmp_path)?;
+            tmp_file.write_all(content)?;
+            tmp_file.sync_all()?;
+            fs::rename(&tmp_path, &path)
+        })();
+        if let Err(e) = result {
+            let _ = fs::remove_file(&tmp_path);
+            // another writer saved the same content first
+            if !path.exists() {
+                return Err(e);
+            }
+        }
+        Ok(hash)
+    }
+
+    /// Read the content of an object,
+    /// the content is verified against the hash to detect corruption.
+    pub fn read(&self, hash: &str) -> Result<Vec<u8>> {
+        let content = fs::read(self.object_path(hash)?)?;
+        if Self::hash(&content) != hash {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("object {} is corrupted", hash),
+            ));
+        }
+        Ok(content)
+    }
+
+    /// The size of an object in bytes.
+    pub fn size(&self, hash: &str) -> Result<u64> {
+        Ok(fs::metadata(self.object_path(hash)?)?.len())
+    }
+
+    /// Check whether an object is in the store.
+    pub fn contains(&self, hash: &str) -> bool {
+        self.object_path(hash).is_ok_and(|path| path.exists())
+    }
+
+    /// Where the object is saved: root/ab/cdef...
+    fn object_path(&self, hash: &str) -> Result<PathBuf> {
+        if hash.len() != 64
+            || !hash
+                .bytes()
+                .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+        {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("invalid object hash: {}", hash),
+            ));
+        }
+        Ok(self.root.join(&hash[..2]).join(&hash[2..]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use self::super::*;
+
+    #[test]
+    // #[ignore]
+    fn test_hash() {
+        assert_eq!(
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            ObjectStore::hash(b"")
+        );
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_write_and_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ObjectStore::new(dir.path());
+        let hash = store.write(b"A\nB\n").unwrap();
+        assert!(store.contains(&hash));
+        assert!(dir.path().join(&hash[..2]).join(&hash[2..]).is_file());
+        assert_eq!(b"A\nB\n".to_vec(), store.read(&hash).unwrap());
+        assert_eq!(4, store.size(&hash).unwrap());
+
+        // deduplicate
+        assert_eq!(hash, store.write(b"A\nB\n").unwrap());
+        assert_eq!(
+            1,
+            fs::read_dir(dir.path().join(&hash[..2])).unwrap().count()
+        );
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_write_concurrently() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ObjectStore::new(dir.path());
+        let hash = ObjectStore::hash(b"A\nB\n");
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| assert_eq!(hash, store.write(b"A\nB\n").unwrap()));
+            }
+        });
+        assert_eq!(b"A\nB\n".to_vec(), store.read(&hash).unwrap());
+        // no temporary file is left
+        assert_eq!(
+            1,
+            fs::read_dir(dir.path().join(&hash[..2])).unwrap().count()
+        );
+    }
+
+    #[test]
+    // #[ignore]
+    fn test_read_invalid() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ObjectStore::new(dir.path());
+        assert_eq!(
+            ErrorKind::InvalidInput,
+            store.read("../../etc/passwd").unwrap_err().kind()
+        );
+        let missing = ObjectStore::hash(b"missing");
+        assert!(!store.contains(&missing));
+        assert_eq!(
+            ErrorKind::NotFound,
+            store.read(&missing).unwrap_err().kind()
+        );
+
+        let hash = store.write(b"A\n").unwrap();
+        fs::write(dir.path().join(&hash[..2]).join(&hash[2..]), b"B\n").unwrap();
+        assert_eq!(
+            ErrorKind::InvalidData,
+            store.read(&hash).unwrap_err().kind()
+        );
+    }
+}
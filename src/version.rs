@@ -0,0 +1,122 @@
+//! This mod defines the Version object.
+//! A Version marks a Commit as a release and records the hash of the full Document,
+//! so the Document at a release is read from the store directly,
+//! instead of replaying every Patch from the first Commit.
+
+use std::io::{Error, ErrorKind, Result};
+
+use crate::macros::s;
+use crate::store::ObjectStore;
+
+/// The version of Version encoding, see `Version::encode`.
+pub const VERSION_VERSION: usize = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Version {
+    /// hash of the released Commit
+    pub commit: String,
+    /// hash of the Document at the released Commit
+    pub document: String,
+    pub notes: String,
+}
+
+impl Version {
+    pub fn new(commit: String, document: String, notes: String) -> Self {
+        Self {
+            commit,
+            document,
+            notes,
+        }
+    }
+
+    /// Encode Version to text, the format is like Commit:
+    /// ```text
+    /// version 1
+    /// commit <commit hash>
+    /// document <document hash>
+    ///
+    /// <release notes>
+    /// ```
+    pub fn encode(&self) -> String {
+        format!(
+            "version {}\ncommit {}\ndocument {}\n\n{}",
+            VERSION_VERSION, self.commit, self.document, self.notes
+        )
+    }
+
+    /// Decode Version from text, see `Version::encode` for the format.
+    pub fn decode(content: &str) -> Result<Self> {
+        let invalid = |msg: String| Error::new(ErrorKind::InvalidData, msg);
+        let (headers, notes) = content
+            .split_once("\n\n")
+            .ok_or_else(|| invalid(s!("release notes are missing")))?;
+
+        let mut version = None;
+        let mut commit = None;
+        let mut document = None;
+        for line in headers.split('\n') {
+            let (key, value) = line
+                .split_once(' ')
+                .ok_or_else(|| invalid(format!("invalid version header: {:?}", line)))?;
+            let field = match key {
+                "version" => &mut version,
+                "commit" => &mut commit,
+                "document" => &mut document,
+                _ => return Err(invalid(format!("unknown version header: {:?}", key))),
+            };
+            if field.replace(s!(value)).is_some() {
+                return Err(invalid(format!("duplicated version header: {:?}", key)));
+            }
+        }
+
+        if version != Some(VERSION_VERSION.to_string()) {
+            return Err(invalid(format!("unsupported version: {:?}", version)));
+        }
+        Ok(Self {
+            commit: commit.ok_or_else(|| invalid(s!("version commit is missing")))?,
+            document: document.ok_or_else(|| invalid(s!("version document is missing")))?,
+            notes: s!(notes),
+        })
+    }
+
+    /// Write Version to store, return the hash.
+    pub fn write_to_store(&self, store: &ObjectStore) -> Result<String> {
+        store.write(self.encode().as_bytes())
+    }
+
+    /// Create a Version from store.
+    pub fn read_from_store(store: &ObjectStore, hash: String) -> Result<Self> {
+        let content = store.read(&hash)?;
+        let content =
+            String::from_utf8(content).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        Self::decode(&content)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use self::super::*;
+
+    #[test]
+    // #[ignore]
+    fn test_encode() {
+        let version = Version::new(s!("c"), s!("d"), s!("v1.0\n\nfirst release"));
+        assert_eq!(
+            s!("version 1\ncommit c\ndocument d\n\nv1.0\n\nfirst release"),
+            version.encode()
+        );
+        assert_eq!(version, Version::decode(&version.encode()).unwrap());
+
+        for content in &[
+            "",
+            "version 1\ncommit c\ndocument d",
+            "version 2\ncommit c\ndocument d\n\n",
+            "version 1\ncommit c\n\n",
+            "version 1\ncommit c\ncommit c\ndocument d\n\n",
+            "version 1\ncommit c\ndocument d\nparent p\n\n",
+        ] {
+            let err = Version::decode(content).unwrap_err();
+            assert_eq!(ErrorKind::InvalidData, err.kind());
+        }
+    }
+}